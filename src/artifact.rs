@@ -0,0 +1,76 @@
+use crate::error::DownloadError;
+use crate::extract::{self, ArchiveKind};
+use just_core::result::BoxedResult;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallerKind {
+    /// Windows Installer package, run by `msiexec`.
+    Msi,
+    /// macOS flat package, run by `installer`.
+    Pkg,
+    /// Debian package, run by `dpkg`.
+    Deb,
+}
+
+impl InstallerKind {
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(&[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1]) {
+            Some(InstallerKind::Msi)
+        } else if magic.starts_with(b"xar!") {
+            Some(InstallerKind::Pkg)
+        } else if magic.starts_with(b"!<arch>\ndebian-binary") {
+            Some(InstallerKind::Deb)
+        } else {
+            None
+        }
+    }
+}
+
+/// What a download yields, which decides how the install step has to handle it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    Archive(ArchiveKind),
+    /// A bare executable.
+    Binary,
+    /// An installer package, to be run by the platform's installer tool.
+    Installer(InstallerKind),
+    /// A self-contained Linux application image.
+    AppImage,
+    Unknown,
+}
+
+impl ArtifactKind {
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        let mut magic = Vec::with_capacity(21);
+        File::open(path)?.take(21).read_to_end(&mut magic)?;
+
+        if let Some(kind) = InstallerKind::from_magic(&magic) {
+            Ok(ArtifactKind::Installer(kind))
+        } else if magic.starts_with(b"\x7fELF") && magic.get(8..11) == Some(&b"AI\x02"[..]) {
+            Ok(ArtifactKind::AppImage)
+        } else if let Some(kind) = ArchiveKind::detect(path)? {
+            Ok(ArtifactKind::Archive(kind))
+        } else if extract::is_executable(path)? {
+            Ok(ArtifactKind::Binary)
+        } else {
+            Ok(ArtifactKind::Unknown)
+        }
+    }
+
+    /// Checks that the artifact at `path` really is of this kind.
+    pub fn verify(self, path: &Path) -> BoxedResult<()> {
+        let detected = Self::detect(path)?;
+        if detected == self {
+            Ok(())
+        } else {
+            Err(DownloadError::Verification(format!(
+                "Expected a {:?} artifact, but {:?} is a {:?}",
+                self, path, detected
+            ))
+            .into())
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Downloads on reqwest's async client, for callers running a futures 0.1 executor such as
+//! tokio 0.1.
+//!
+//! The version is resolved, the destination picked and the artifact checked and finished
+//! like the blocking `download_with_options` does. What still needs blocking requests is
+//! rejected instead: version discovery, mirrors, segments, conditional and offline downloads,
+//! signatures, provenance, TUF, scanners, companion files and middlewares. Downloads aren't
+//! retried or locked against other processes, `CollisionPolicy::Reuse` only keeps artifacts
+//! matching a checksum, and no progress bar is drawn. The artifact is written with blocking
+//! file IO on the executor, like tokio 0.1's `fs` does.
+
+use crate::error;
+use crate::events::DownloadEvent;
+use crate::options::DownloadOptions;
+use crate::platform;
+use crate::versions::VersionSelector;
+use crate::warning::DownloadWarning;
+use crate::{accepts_ranges, verify_checksum, verify_kind};
+use crate::{content_length, existing, finalize, finished, last_modified, part_path, resolve};
+use crate::{DownloadInfo, DownloadPath, DownloadStatus, ErrorCategory, ResolvedDownload};
+use futures::future::{self, Either, Future};
+use futures::Stream;
+use just_core::manifest::Manifest;
+use just_core::result::BoxedResult;
+use reqwest::r#async::{Client, Response};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+enum Prepared<'a> {
+    /// The artifact in the destination, kept by the collision policy.
+    Existing(DownloadInfo<'a>),
+    Transfer {
+        client: Client,
+        resolved: ResolvedDownload,
+        download_path: DownloadPath,
+        /// With the checksum of the resolved version.
+        options: DownloadOptions,
+    },
+}
+
+/// What the response tells about the artifact before its body is read.
+struct Announced {
+    size: Option<u64>,
+    accepts_ranges: bool,
+    last_modified: Option<SystemTime>,
+    insecure_transport: bool,
+}
+
+/// Like the blocking `download_with_options`, as a future. Fails with a
+/// `DownloadError::Failed` like it, also if `options` ask for something only the blocking
+/// download does.
+pub fn download_with_options<'a>(
+    manifest: &'a Manifest,
+    req: impl Into<VersionSelector>,
+    options: &DownloadOptions,
+) -> impl Future<Item = DownloadInfo<'a>, Error = Box<dyn Error>> + 'a {
+    let selector = req.into();
+    let options = options.clone();
+
+    let prepared = prepare(manifest, &selector, &options);
+    let transferred = future::result(prepared).and_then({
+        let selector = selector.clone();
+        move |prepared| match prepared {
+            Prepared::Existing(download_info) => Either::A(future::ok(download_info)),
+            Prepared::Transfer {
+                client,
+                resolved,
+                download_path,
+                options,
+            } => Either::B(transfer(
+                client,
+                manifest,
+                selector,
+                resolved,
+                download_path,
+                options,
+            )),
+        }
+    });
+
+    transferred.then(move |result| {
+        let package = manifest.package.name.as_str();
+        let result = result.map_err(|e| error::with_context(e, package, &selector, None));
+        finished(package, &result, &options);
+
+        result
+    })
+}
+
+/// What `download_with_options` can't do without blocking, if `options` ask for it.
+fn unsupported(options: &DownloadOptions) -> Option<&'static str> {
+    #[cfg(feature = "tuf")]
+    let tuf = options.tuf.is_some();
+    #[cfg(not(feature = "tuf"))]
+    let tuf = false;
+    #[cfg(feature = "blocking")]
+    let bandwidth = options.bandwidth.is_some();
+    #[cfg(not(feature = "blocking"))]
+    let bandwidth = false;
+
+    let unsupported = [
+        ("version_source", options.version_source.is_some()),
+        ("mirrors", !options.mirrors.is_empty()),
+        ("segments", options.segments > 1),
+        ("swarm", options.swarm),
+        ("if_newer", options.if_newer),
+        ("offline", options.offline),
+        ("force", options.force),
+        ("content_disposition", options.content_disposition),
+        ("decode_content", options.decode_content),
+        ("signatures", options.signatures.is_some()),
+        ("provenance", options.provenance.is_some()),
+        ("tuf", tuf),
+        ("scanner", options.scanner.is_some()),
+        ("before_download", options.before_download.is_some()),
+        ("middlewares", !options.middlewares.is_empty()),
+        ("har", options.har.is_some()),
+        ("sbom_url", options.sbom_url.is_some()),
+        (
+            "changelog",
+            options.changelog || options.changelog_url.is_some(),
+        ),
+        ("license_url", options.license_url.is_some()),
+        ("archive_fallback", options.archive_fallback),
+        (
+            "max_bytes_per_second",
+            options.max_bytes_per_second.is_some(),
+        ),
+        ("bandwidth", bandwidth),
+    ];
+
+    unsupported
+        .iter()
+        .find(|(_, set)| *set)
+        .map(|(name, _)| *name)
+}
+
+/// Resolves the version and picks the destination, which may be kept as it is.
+fn prepare<'a>(
+    manifest: &'a Manifest,
+    selector: &VersionSelector,
+    options: &DownloadOptions,
+) -> BoxedResult<Prepared<'a>> {
+    let package = manifest.package.name.as_str();
+    options.check_deadline()?;
+    if let Some(option) = unsupported(options) {
+        return Err(format!("`{}` requires the blocking download", option).into());
+    }
+
+    let resolved = resolve(manifest, selector, options)?;
+    let (download_url, version) = (resolved.url.as_str(), &resolved.version);
+    let options = &match options.checksum_for(version) {
+        Some(checksum) => DownloadOptions {
+            checksum: Some(checksum.clone()),
+            ..options.clone()
+        },
+        None => options.clone(),
+    };
+    let mut download_path = DownloadPath::from(download_url)?;
+    download_path.compressed_path =
+        options.destination(&download_path.compressed_path, package, version);
+    options.emit(|| DownloadEvent::Resolved {
+        package: package.to_owned(),
+        version: version.clone(),
+        url: download_url.to_owned(),
+    });
+
+    // A size check would need a blocking HEAD request.
+    let intact = |artifact: &Path| {
+        options.checksum.as_ref().map_or(false, |checksum| {
+            checksum
+                .verify(artifact, options.allow_insecure_digests)
+                .is_ok()
+        })
+    };
+    let existing = existing(
+        manifest,
+        version,
+        download_url,
+        &mut download_path,
+        options,
+        intact,
+    )?;
+    if let Some(download_info) = existing {
+        return Ok(Prepared::Existing(download_info));
+    }
+
+    let client = options
+        .configure(Client::builder(), download_url)?
+        .build()?;
+    Ok(Prepared::Transfer {
+        client,
+        resolved,
+        download_path,
+        options: options.clone(),
+    })
+}
+
+/// Transfers the artifact into a `.part` file, and verifies and finishes it.
+fn transfer<'a>(
+    client: Client,
+    manifest: &'a Manifest,
+    selector: VersionSelector,
+    resolved: ResolvedDownload,
+    download_path: DownloadPath,
+    options: DownloadOptions,
+) -> impl Future<Item = DownloadInfo<'a>, Error = Box<dyn Error>> + 'a {
+    use log::info;
+
+    let started = Instant::now();
+    let part_path = part_path(&platform::long_path(&download_path.compressed_path));
+    info!("Downloading {}...", resolved.url);
+    options.report(&resolved.url, |telemetry, host| telemetry.started(host));
+
+    let written = {
+        let (part_path, options) = (part_path.clone(), options.clone());
+        let package = manifest.package.name.clone();
+        client
+            .get(&resolved.url)
+            .send()
+            .and_then(Response::error_for_status)
+            .map_err(|e| -> Box<dyn Error> { e.into() })
+            .and_then(move |response| {
+                let announced = announce(&response, &options);
+                future::result(announced).and_then(move |announced| {
+                    write(response, part_path, package, announced.size, options)
+                        .map(|written| (announced, written))
+                })
+            })
+    };
+
+    written.then(move |written| {
+        let result = written.and_then(|(announced, written)| {
+            finish(
+                manifest,
+                &resolved,
+                &download_path,
+                &part_path,
+                announced,
+                written,
+                &options,
+            )
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&part_path);
+        }
+        options.report(&resolved.url, |telemetry, host| match &result {
+            Ok(download_info) => {
+                telemetry.succeeded(host, download_info.encoded_size, started.elapsed())
+            }
+            Err(e) => telemetry.failed(host, ErrorCategory::of(&**e), started.elapsed()),
+        });
+
+        result.map_err(|e| {
+            let resolved = Some((resolved.url.as_str(), &resolved.version));
+            error::with_context(e, &manifest.package.name, &selector, resolved)
+        })
+    })
+}
+
+/// Checks the headers of `response` before its body is transferred.
+fn announce(response: &Response, options: &DownloadOptions) -> BoxedResult<Announced> {
+    use crate::error::DownloadError;
+    use reqwest::header::CONTENT_TYPE;
+
+    let headers = response.headers();
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok());
+    if !options.accepts_content_type(content_type) {
+        return Err(DownloadError::Verification(format!(
+            "Unexpected Content-Type {:?} from {}, expected one of {:?}",
+            content_type,
+            response.url(),
+            options.expected_content_types
+        ))
+        .into());
+    }
+
+    Ok(Announced {
+        size: content_length(headers),
+        accepts_ranges: accepts_ranges(headers),
+        last_modified: last_modified(headers),
+        insecure_transport: response.url().scheme() == "http",
+    })
+}
+
+/// Writes the body of `response` into `part_path`, returning the bytes written.
+fn write(
+    response: Response,
+    part_path: PathBuf,
+    package: String,
+    total: Option<u64>,
+    options: DownloadOptions,
+) -> impl Future<Item = u64, Error = Box<dyn Error>> {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let file = part_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| File::create(&part_path))
+        .map_err(|e| -> Box<dyn Error> { e.into() });
+    future::result(file)
+        .and_then(move |file| {
+            response
+                .into_body()
+                .map_err(|e| -> Box<dyn Error> { e.into() })
+                .fold((file, 0), move |(mut file, transferred), chunk| {
+                    let written = options.check_deadline().and_then(|()| {
+                        file.write_all(chunk.as_ref())?;
+                        Ok(transferred + chunk.as_ref().len() as u64)
+                    });
+                    written.map(|transferred| {
+                        options.emit(|| DownloadEvent::Progress {
+                            package: package.clone(),
+                            transferred,
+                            total,
+                        });
+                        (file, transferred)
+                    })
+                })
+        })
+        .map(|(_, transferred)| transferred)
+}
+
+/// Verifies the transferred artifact at `part_path` and moves it to its destination.
+fn finish<'a>(
+    manifest: &'a Manifest,
+    resolved: &ResolvedDownload,
+    download_path: &DownloadPath,
+    part_path: &Path,
+    announced: Announced,
+    written: u64,
+    options: &DownloadOptions,
+) -> BoxedResult<DownloadInfo<'a>> {
+    use log::info;
+
+    if let Some(size) = announced.size.filter(|size| *size != written) {
+        return Err(format!(
+            "Transferred {} of {} bytes from {}",
+            written, size, resolved.url
+        )
+        .into());
+    }
+
+    let mut warnings = Vec::new();
+    verify_checksum(part_path, options, &mut warnings)?;
+    let artifact_kind = verify_kind(part_path, options)?;
+    if announced.insecure_transport {
+        warnings.push(DownloadWarning::InsecureTransport {
+            url: resolved.url.clone(),
+        });
+    }
+    finalize(
+        part_path,
+        &platform::long_path(&download_path.compressed_path),
+        &resolved.url,
+        artifact_kind,
+        announced.last_modified,
+        options,
+        &mut warnings,
+    )?;
+    info!(
+        "Download of '{}' has been completed.",
+        manifest.package.name.as_str()
+    );
+
+    Ok(DownloadInfo {
+        package: &manifest.package,
+        version: resolved.version.clone(),
+        url: resolved.url.clone(),
+        size: written,
+        encoded_size: written,
+        compressed_path: download_path.compressed_path.clone(),
+        uncompressed_path: download_path.uncompressed_path.clone(),
+        accepts_ranges: announced.accepts_ranges,
+        status: DownloadStatus::Downloaded,
+        content_decoded: false,
+        artifact_kind,
+        staged_path: None,
+        provenance: None,
+        sbom_path: None,
+        changelog_path: None,
+        license_path: None,
+        warnings,
+    })
+}
@@ -0,0 +1,156 @@
+//! A bandwidth cap shared by concurrent downloads.
+
+use crate::options::DownloadOptions;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Packages which haven't transferred anything for this long don't get a share anymore.
+const IDLE: Duration = Duration::from_secs(1);
+
+/// A token bucket refilled at `bytes_per_second`, holding up to a second worth of bytes.
+/// Clones of an `Arc` of it cap the aggregate rate of every download using them.
+///
+/// The rate is split evenly between the packages currently transferring, so an artifact
+/// downloaded in many segments doesn't starve the others.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: u64,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    total: Bucket,
+    packages: HashMap<String, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Negative while transfers wait for the bytes they already took.
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: 0.0,
+            refilled: now,
+        }
+    }
+
+    /// Refills the bucket at `rate`, takes `bytes` out of it and returns how long to wait
+    /// for them.
+    fn take(&mut self, bytes: u64, rate: f64, now: Instant) -> Duration {
+        let refill = now.duration_since(self.refilled).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(rate) - bytes as f64;
+        self.refilled = now;
+
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        self.tokens >= 0.0 && now.duration_since(self.refilled) > IDLE
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            buckets: Mutex::new(Buckets {
+                total: Bucket::new(now),
+                packages: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Takes `bytes` of `package` out of the bucket, sleeping until they have been refilled
+    /// if it, or the share of `package`, runs dry.
+    pub(crate) fn acquire(&self, package: &str, bytes: u64) {
+        let rate = self.bytes_per_second as f64;
+        let wait = {
+            let mut buckets = self
+                .buckets
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let now = Instant::now();
+            buckets.packages.retain(|_, bucket| !bucket.is_idle(now));
+            if !buckets.packages.contains_key(package) {
+                buckets
+                    .packages
+                    .insert(package.to_owned(), Bucket::new(now));
+            }
+
+            let share = rate / buckets.packages.len() as f64;
+            let total = buckets.total.take(bytes, rate, now);
+            let package = buckets
+                .packages
+                .get_mut(package)
+                .map(|bucket| bucket.take(bytes, share, now))
+                .unwrap_or_default();
+
+            total.max(package)
+        };
+
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Waits for the shared bandwidth limiter, if there is one, to allow `bytes` more
+    /// of `package`.
+    pub(crate) fn throttle(&self, package: &str, bytes: u64) {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(package, bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_bytes_taken() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(start);
+        assert_eq!(bucket.take(100, 100.0, start), Duration::from_secs(1));
+        assert_eq!(
+            bucket.take(50, 100.0, start + Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn holds_at_most_a_second_of_bytes() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(start);
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.take(100, 100.0, later), Duration::from_secs(0));
+        assert_eq!(bucket.take(50, 100.0, later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn becomes_idle_once_paid_off() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(start);
+        bucket.take(100, 100.0, start);
+        assert!(!bucket.is_idle(start + Duration::from_millis(1500)));
+        bucket.take(0, 100.0, start + Duration::from_secs(2));
+        assert!(bucket.is_idle(start + Duration::from_secs(4)));
+    }
+}
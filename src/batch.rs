@@ -0,0 +1,794 @@
+//! Operations on a whole set of packages, e.g. everything `just install` was asked for.
+
+use crate::bandwidth::BandwidthLimiter;
+use crate::events::{DownloadEvent, EventListener};
+use crate::metrics::{MetricsSink, Phase};
+use crate::options::DownloadOptions;
+use crate::progress::{self, ProgressBar};
+use crate::retry_budget::RetryBudget;
+use crate::slots::TransferSlots;
+use crate::{download_with_options, probe_with_options, resolve};
+use crate::{DownloadInfo, DownloadStatus, ProbeInfo};
+use just_core::manifest::{Manifest, Package};
+use semver::{Version, VersionReq};
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub use crate::slots::Priority;
+
+/// Threads working on a batch at the same time.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// The version of a package `resolve_all` picked, and where it's downloaded from.
+#[derive(Debug, Clone)]
+pub struct Resolution<'a> {
+    pub package: &'a Package,
+    pub req: Option<VersionReq>,
+    /// The matching version and its URL, or why there's none, e.g. no version matching `req`
+    /// or only ones older than the minimum version.
+    pub resolved: Result<(Version, String), String>,
+}
+
+/// The outcome of resolving every package of a batch, in the order they were given.
+#[derive(Debug, Clone)]
+pub struct ResolutionReport<'a> {
+    pub resolutions: Vec<Resolution<'a>>,
+}
+
+impl<'a> ResolutionReport<'a> {
+    /// The packages without a matching version.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Resolution<'a>> {
+        self.resolutions
+            .iter()
+            .filter(|resolution| resolution.resolved.is_err())
+    }
+
+    /// Whether every package can be downloaded.
+    pub fn is_complete(&self) -> bool {
+        self.unresolved().next().is_none()
+    }
+}
+
+/// Resolves the versions and URLs of all `requests` concurrently, before anything is downloaded.
+pub fn resolve_all<'a>(requests: &[(&'a Manifest, Option<VersionReq>)]) -> ResolutionReport<'a> {
+    resolve_all_with_options(requests, &DownloadOptions::default())
+}
+
+/// Like `resolve_all`, but resolving versions as `options` ask for.
+pub fn resolve_all_with_options<'a>(
+    requests: &[(&'a Manifest, Option<VersionReq>)],
+    options: &DownloadOptions,
+) -> ResolutionReport<'a> {
+    let resolutions = parallel_map(requests, options.batch_concurrency(), |(manifest, req)| {
+        Resolution {
+            package: &manifest.package,
+            req: req.clone(),
+            resolved: resolve(manifest, &req.clone().into(), options)
+                .map(|resolved| (resolved.version, resolved.url))
+                .map_err(|e| e.to_string()),
+        }
+    });
+
+    ResolutionReport { resolutions }
+}
+
+/// The size of a package `estimate` asked the server about.
+pub struct PackageSize<'a> {
+    pub package: &'a Package,
+    /// What the server told about the artifact, or why it couldn't be asked.
+    pub probe: Result<ProbeInfo, String>,
+}
+
+impl<'a> PackageSize<'a> {
+    pub fn size(&self) -> Option<u64> {
+        self.probe.as_ref().ok().and_then(|probe| probe.size)
+    }
+}
+
+/// The bytes a batch is going to download, e.g. to ask the user before starting.
+pub struct SizeEstimate<'a> {
+    /// The sum of every known size.
+    pub total: u64,
+    pub packages: Vec<PackageSize<'a>>,
+}
+
+impl<'a> SizeEstimate<'a> {
+    /// The packages whose size isn't part of `total`, because the server didn't tell it
+    /// or couldn't be asked.
+    pub fn unknown(&self) -> impl Iterator<Item = &PackageSize<'a>> {
+        self.packages
+            .iter()
+            .filter(|package| package.size().is_none())
+    }
+}
+
+/// Asks the servers of all `requests` concurrently how large their artifacts are.
+pub fn estimate<'a>(
+    requests: &[(&'a Manifest, Option<VersionReq>)],
+    options: &DownloadOptions,
+) -> SizeEstimate<'a> {
+    let packages = parallel_map(requests, options.batch_concurrency(), |(manifest, req)| {
+        PackageSize {
+            package: &manifest.package,
+            probe: probe_with_options(manifest, req.clone(), options).map_err(|e| e.to_string()),
+        }
+    });
+
+    SizeEstimate {
+        total: packages.iter().filter_map(PackageSize::size).sum(),
+        packages,
+    }
+}
+
+/// Downloads a batch of packages concurrently.
+///
+/// Next to the bar of every package, an overall bar shows the bytes of the whole batch,
+/// which are reported as `DownloadEvent::BatchProgress` as well.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadManager {
+    options: DownloadOptions,
+    budget: Option<u64>,
+    unknown_sizes_fit: bool,
+}
+
+/// What happened to every package of a batch, e.g. for a closing summary.
+pub struct BatchReport<'a> {
+    /// The packages attempted, in the order they were given.
+    pub downloads: Vec<(&'a Package, Result<DownloadInfo<'a>, String>)>,
+    /// The packages left out because they would have exceeded the budget.
+    pub skipped: Vec<&'a Package>,
+    /// The bytes transferred, not counting artifacts that were up to date.
+    pub total_bytes: u64,
+    /// Downloads that found their artifact up to date.
+    pub cache_hits: usize,
+    /// Attempts made again after a failure, over all downloads.
+    pub retries: usize,
+    /// The wall-clock time of the whole batch.
+    pub elapsed: Duration,
+    /// The host with the lowest throughput and its bytes per second.
+    pub slowest_host: Option<(String, f64)>,
+}
+
+impl<'a> BatchReport<'a> {
+    /// The packages which couldn't be downloaded, with the reason.
+    pub fn failed(&self) -> impl Iterator<Item = (&'a Package, &String)> {
+        self.downloads
+            .iter()
+            .filter_map(|(package, result)| result.as_ref().err().map(|e| (*package, e)))
+    }
+}
+
+impl<'a> fmt::Display for BatchReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use crate::options::{RateUnit, UnitPrefix};
+        use crate::progress::format_duration;
+        use crate::speed::{format_rate, format_size};
+
+        write!(
+            f,
+            "{} downloaded, {} failed, {} skipped, {} up to date: {} in {} with {} retries",
+            self.downloads.len() - self.failed().count() - self.cache_hits,
+            self.failed().count(),
+            self.skipped.len(),
+            self.cache_hits,
+            format_size(self.total_bytes, UnitPrefix::Binary),
+            format_duration(self.elapsed),
+            self.retries
+        )?;
+        if let Some((host, speed)) = &self.slowest_host {
+            write!(
+                f,
+                ", slowest host {} at {}",
+                host,
+                format_rate(*speed, RateUnit::Bytes, UnitPrefix::Binary)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts the retries of a batch, passing every measurement on to the caller's sink.
+struct BatchMetrics {
+    inner: Option<Arc<dyn MetricsSink>>,
+    retries: AtomicUsize,
+}
+
+impl MetricsSink for BatchMetrics {
+    fn bytes_transferred(&self, package: &str, bytes: u64) {
+        if let Some(inner) = &self.inner {
+            inner.bytes_transferred(package, bytes);
+        }
+    }
+
+    fn phase_finished(&self, package: &str, phase: Phase, duration: Duration) {
+        if let Some(inner) = &self.inner {
+            inner.phase_finished(package, phase, duration);
+        }
+    }
+
+    fn retried(&self, package: &str, attempt: usize) {
+        self.retries.fetch_add(1, Ordering::SeqCst);
+        if let Some(inner) = &self.inner {
+            inner.retried(package, attempt);
+        }
+    }
+
+    fn cache_hit(&self, package: &str) {
+        if let Some(inner) = &self.inner {
+            inner.cache_hit(package);
+        }
+    }
+
+    fn speed(&self, package: &str, bytes_per_second: f64, eta: Option<Duration>) {
+        if let Some(inner) = &self.inner {
+            inner.speed(package, bytes_per_second, eta);
+        }
+    }
+}
+
+/// Draws the overall bar of a batch and reports it as `DownloadEvent::BatchProgress`.
+struct BatchProgress {
+    inner: Option<Arc<dyn EventListener>>,
+    bar: ProgressBar,
+    /// The bytes transferred and the size of every package, by name.
+    packages: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl EventListener for BatchProgress {
+    fn on_event(&self, event: &DownloadEvent) {
+        if let Some(inner) = &self.inner {
+            inner.on_event(event);
+        }
+
+        let mut packages = self.packages.lock().unwrap();
+        match event {
+            DownloadEvent::Started {
+                package,
+                size: Some(size),
+                ..
+            } => packages.entry(package.clone()).or_default().1 = *size,
+            // Starts over from 0 when a download is attempted again.
+            DownloadEvent::Progress {
+                package,
+                transferred,
+                ..
+            } => packages.entry(package.clone()).or_default().0 = *transferred,
+            _ => return,
+        }
+        let transferred = packages.values().map(|(transferred, _)| transferred).sum();
+        let total = packages.values().map(|(_, size)| size).sum();
+        drop(packages);
+
+        self.bar.set_length(total);
+        self.bar.set_position(transferred);
+        if let Some(inner) = &self.inner {
+            inner.on_event(&DownloadEvent::BatchProgress { transferred, total });
+        }
+    }
+}
+
+impl DownloadManager {
+    /// Shares the `transfer_slots` of `options` between its clones, or makes new ones for
+    /// as many downloads as it runs at once.
+    pub fn new(mut options: DownloadOptions) -> Self {
+        if options.transfer_slots.is_none() {
+            let slots = TransferSlots::new(options.batch_concurrency());
+            options.transfer_slots = Some(Arc::new(slots));
+        }
+
+        Self {
+            options,
+            budget: None,
+            unknown_sizes_fit: false,
+        }
+    }
+
+    /// Caps the bandwidth of all downloads of the batch together at `bytes_per_second`,
+    /// unlike `max_bytes_per_second` which caps every download on its own.
+    pub fn with_bandwidth_limit(mut self, bytes_per_second: u64) -> Self {
+        self.options.bandwidth = Some(Arc::new(BandwidthLimiter::new(bytes_per_second)));
+        self
+    }
+
+    /// Cancels the downloads of the batch still running at `deadline`, and fails the ones
+    /// not started yet.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.options.deadline = Some(deadline);
+        self
+    }
+
+    /// Shares `retry_budget` between all downloads of the batch. Once it's exhausted,
+    /// failing downloads aren't retried and the packages not started yet fail right away.
+    pub fn with_retry_budget(mut self, retry_budget: RetryBudget) -> Self {
+        self.options.retry_budget = Some(Arc::new(retry_budget));
+        self
+    }
+
+    /// Stops the batch before it downloads more than `bytes`, for metered connections.
+    pub fn with_budget(mut self, bytes: u64) -> Self {
+        self.budget = Some(bytes);
+        self
+    }
+
+    /// Lets packages whose size can't be estimated into the budget, counting them as 0 bytes.
+    /// By default they don't fit, as nothing bounds what they transfer.
+    pub fn with_unknown_sizes_fitting(mut self) -> Self {
+        self.unknown_sizes_fit = true;
+        self
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    /// Like `download_all`, but starting the packages of a higher priority first, as slots
+    /// become free. A budget is spent on them first as well.
+    ///
+    /// The transfer slots are shared with the batches of clones of the manager, so a package
+    /// the user asked for preempts the prefetches of a batch running in the background: if
+    /// every slot is taken, the lowest download running is paused until a slot is free.
+    ///
+    /// The report still lists the packages in the order they were given.
+    pub fn download_prioritized<'a>(
+        &self,
+        requests: &[(&'a Manifest, Option<VersionReq>, Priority)],
+    ) -> BatchReport<'a> {
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|index| cmp::Reverse(requests[*index].2));
+        let scheduled: Vec<_> = order
+            .iter()
+            .map(|index| (requests[*index].0, requests[*index].1.clone()))
+            .collect();
+        let priorities: Vec<_> = order.iter().map(|index| requests[*index].2).collect();
+
+        let mut report = self.download_scheduled(&scheduled, &[], &priorities);
+        let position = |package: &Package| {
+            requests
+                .iter()
+                .position(|(manifest, _, _)| std::ptr::eq(&manifest.package, package))
+        };
+        report
+            .downloads
+            .sort_by_key(|(package, _)| position(package));
+        report.skipped.sort_by_key(|package| position(package));
+
+        report
+    }
+
+    /// Like `download_all`, but starting a package only once the packages of the batch it
+    /// depends on have been downloaded. `dependencies` maps package names to the names of
+    /// their dependencies, the ones outside of the batch are assumed to be installed.
+    ///
+    /// A `DownloadEvent::Ready` is emitted per package once it can be installed, while the
+    /// rest of the batch continues. Packages whose dependencies failed aren't downloaded.
+    pub fn download_in_dependency_order<'a>(
+        &self,
+        requests: &[(&'a Manifest, Option<VersionReq>)],
+        dependencies: &HashMap<String, Vec<String>>,
+    ) -> BatchReport<'a> {
+        let index = |name: &str| {
+            requests
+                .iter()
+                .position(|(manifest, _)| manifest.package.name == name)
+        };
+        let prerequisites: Vec<Vec<usize>> = requests
+            .iter()
+            .map(|(manifest, _)| {
+                dependencies
+                    .get(manifest.package.name.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|name| index(name))
+                    .collect()
+            })
+            .collect();
+
+        self.download_scheduled(requests, &prerequisites, &[])
+    }
+
+    /// Downloads all `requests`, skipping the rest of the batch once the budget is exhausted.
+    ///
+    /// Downloads started while packages are still queued use a single connection, and a
+    /// bandwidth limit is split evenly between the packages transferring, so a large
+    /// artifact doesn't starve the small ones queued behind it.
+    ///
+    /// With a budget, the sizes are estimated first and the batch is cut at the first package
+    /// that doesn't fit. While downloading, every package reserves its estimate until it's
+    /// done, when the bytes actually transferred take its place, and packages are skipped if
+    /// their estimate exceeds what's left.
+    pub fn download_all<'a>(
+        &self,
+        requests: &[(&'a Manifest, Option<VersionReq>)],
+    ) -> BatchReport<'a> {
+        self.download_scheduled(requests, &[], &[])
+    }
+
+    /// Downloads `requests`, each once the ones at its `prerequisites` are done, and with
+    /// the transfer slots of its `priorities`, `Priority::Normal` where there's none.
+    fn download_scheduled<'a>(
+        &self,
+        requests: &[(&'a Manifest, Option<VersionReq>)],
+        prerequisites: &[Vec<usize>],
+        priorities: &[Priority],
+    ) -> BatchReport<'a> {
+        use log::warn;
+
+        let started = Instant::now();
+        let metrics = Arc::new(BatchMetrics {
+            inner: self.options.metrics.clone(),
+            retries: AtomicUsize::new(0),
+        });
+        let batch_progress = Arc::new(BatchProgress {
+            inner: self.options.events.clone(),
+            bar: progress::bar(Some(0), &self.options),
+            packages: Mutex::new(HashMap::new()),
+        });
+        let options = DownloadOptions {
+            metrics: Some(metrics.clone()),
+            events: Some(batch_progress.clone()),
+            ..self.options.clone()
+        };
+
+        let (planned, mut skipped) = match self.budget {
+            Some(budget) => self.plan(requests, budget),
+            None => (
+                requests
+                    .iter()
+                    .map(|(manifest, req)| (*manifest, req.clone(), None))
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+
+        // While packages are queued, segments and mirrors would take connections from them.
+        let single_connection = DownloadOptions {
+            segments: 0,
+            swarm: false,
+            ..options.clone()
+        };
+        let planned: Vec<_> = planned
+            .into_iter()
+            .enumerate()
+            .map(|(index, (manifest, req, size))| {
+                let priority = priorities.get(index).copied().unwrap_or_default();
+                (manifest, req, size, priority)
+            })
+            .collect();
+        let dispatched = AtomicUsize::new(0);
+        // The bytes transferred by the finished downloads and reserved by the running ones.
+        let spent = Mutex::new(0u64);
+        let hosts: Mutex<HashMap<String, (u64, Duration)>> = Mutex::new(HashMap::new());
+        let skipped_while_downloading = Mutex::new(Vec::new());
+        let downloads = dependent_map(
+            &planned,
+            prerequisites,
+            self.options.batch_concurrency(),
+            |(manifest, req, size, priority), dependencies_downloaded| {
+                let queued = dispatched.fetch_add(1, Ordering::SeqCst) + 1 < planned.len();
+                if !dependencies_downloaded {
+                    let failed = Err("A dependency could not be downloaded".to_owned());
+                    return Some((&manifest.package, failed));
+                }
+                let exhausted = options
+                    .retry_budget
+                    .as_ref()
+                    .and_then(|retry_budget| retry_budget.exhausted());
+                if let Some(reason) = exhausted {
+                    let failed = Err(format!("Not downloaded, {}", reason));
+                    return Some((&manifest.package, failed));
+                }
+                if let Some(budget) = self.budget {
+                    // Checked and reserved under one lock, so downloads starting together
+                    // can't all fit into what's left.
+                    let mut spent = spent.lock().unwrap();
+                    let expected = *spent + size.unwrap_or(0);
+                    if expected > budget {
+                        skipped_while_downloading
+                            .lock()
+                            .unwrap()
+                            .push(&manifest.package);
+                        return None;
+                    }
+                    *spent = expected;
+                }
+
+                let _slot = options
+                    .transfer_slots
+                    .as_ref()
+                    .map(|transfer_slots| transfer_slots.enter(&manifest.package.name, *priority));
+                let options = if queued { &single_connection } else { &options };
+                let downloading = Instant::now();
+                let result = download_with_options(manifest, req.clone(), options)
+                    .map_err(|e| e.to_string());
+                if self.budget.is_some() {
+                    let mut spent = spent.lock().unwrap();
+                    *spent -= size.unwrap_or(0);
+                    if let Ok(download_info) = &result {
+                        *spent += download_info.encoded_size;
+                    }
+                }
+                if let Ok(download_info) = &result {
+                    let host = host(&download_info.url)
+                        .filter(|_| download_info.status != DownloadStatus::NotModified);
+                    if let Some(host) = host {
+                        let mut hosts = hosts.lock().unwrap();
+                        let (bytes, elapsed) = hosts.entry(host).or_default();
+                        *bytes += download_info.encoded_size;
+                        *elapsed += downloading.elapsed();
+                    }
+                    options.emit(|| DownloadEvent::Ready {
+                        package: manifest.package.name.clone(),
+                        path: download_info.compressed_path.clone(),
+                    });
+                }
+
+                Some((&manifest.package, result))
+            },
+            |download| matches!(download, Some((_, Ok(_)))),
+        );
+
+        batch_progress.bar.finish();
+        skipped.append(&mut skipped_while_downloading.into_inner().unwrap());
+        if !skipped.is_empty() {
+            warn!(
+                "Download budget exhausted, skipped {}",
+                skipped
+                    .iter()
+                    .map(|package| package.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let downloads: Vec<_> = downloads.into_iter().flatten().collect();
+        let up_to_date =
+            |download_info: &DownloadInfo| download_info.status == DownloadStatus::NotModified;
+        let successful = || {
+            downloads
+                .iter()
+                .filter_map(|(_, result)| result.as_ref().ok())
+        };
+        let slowest_host = hosts
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|(_, (_, elapsed))| *elapsed > Duration::from_secs(0))
+            .map(|(host, (bytes, elapsed))| (host, bytes as f64 / elapsed.as_secs_f64()))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+
+        BatchReport {
+            total_bytes: successful()
+                .filter(|download_info| !up_to_date(download_info))
+                .map(|download_info| download_info.encoded_size)
+                .sum(),
+            cache_hits: successful()
+                .filter(|download_info| up_to_date(download_info))
+                .count(),
+            retries: metrics.retries.load(Ordering::SeqCst),
+            elapsed: started.elapsed(),
+            slowest_host,
+            downloads,
+            skipped,
+        }
+    }
+
+    /// Splits `requests` into the ones fitting into `budget`, with their estimated size,
+    /// and the skipped rest.
+    #[allow(clippy::type_complexity)]
+    fn plan<'a>(
+        &self,
+        requests: &[(&'a Manifest, Option<VersionReq>)],
+        budget: u64,
+    ) -> (
+        Vec<(&'a Manifest, Option<VersionReq>, Option<u64>)>,
+        Vec<&'a Package>,
+    ) {
+        let estimate = estimate(requests, &self.options);
+        let mut planned = Vec::new();
+        let mut skipped = Vec::new();
+        let mut total = 0;
+        for ((manifest, req), package) in requests.iter().zip(&estimate.packages) {
+            let size = package.size();
+            let fits = size.is_some() || self.unknown_sizes_fit;
+            total += size.unwrap_or(0);
+            if total > budget || !fits || !skipped.is_empty() {
+                skipped.push(&manifest.package);
+            } else {
+                planned.push((*manifest, req.clone(), size));
+            }
+        }
+
+        (planned, skipped)
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    use reqwest::Url;
+
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+}
+
+impl DownloadOptions {
+    pub(crate) fn batch_concurrency(&self) -> usize {
+        match self.concurrency {
+            0 => DEFAULT_CONCURRENCY,
+            concurrency => concurrency,
+        }
+    }
+}
+
+/// Like `parallel_map`, but applying `f` to an item only once it has been applied to the
+/// items at its `prerequisites`, telling `f` whether `succeeded` all of them.
+/// Items in a dependency cycle are started as if a prerequisite failed.
+pub(crate) fn dependent_map<T, R, F, S>(
+    items: &[T],
+    prerequisites: &[Vec<usize>],
+    concurrency: usize,
+    f: F,
+    succeeded: S,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T, bool) -> R + Sync,
+    S: Fn(&R) -> bool + Sync,
+{
+    use std::sync::Condvar;
+    use std::thread;
+
+    struct Schedule {
+        started: Vec<bool>,
+        /// Whether the item succeeded, once it's done.
+        done: Vec<Option<bool>>,
+        running: usize,
+    }
+
+    let prerequisites_of = |index: usize| prerequisites.get(index).map_or(&[][..], Vec::as_slice);
+    let schedule = Mutex::new(Schedule {
+        started: vec![false; items.len()],
+        done: vec![None; items.len()],
+        running: 0,
+    });
+    let changed = Condvar::new();
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(items.len()) {
+            scope.spawn(|| loop {
+                let mut guard = schedule.lock().unwrap();
+                let (index, prerequisites_succeeded) = loop {
+                    let state: &Schedule = &guard;
+                    let unstarted = move || (0..items.len()).filter(move |i| !state.started[*i]);
+                    // Prerequisites outside of `items`, e.g. cut by a budget, count as failed.
+                    let ready = unstarted().find(|index| {
+                        prerequisites_of(*index).iter().all(|prerequisite| {
+                            state.done.get(*prerequisite).map_or(true, Option::is_some)
+                        })
+                    });
+                    let next = match ready {
+                        Some(index) => Some(index),
+                        None if state.running == 0 => unstarted().next(),
+                        None => None,
+                    };
+                    match next {
+                        Some(index) => {
+                            let prerequisites_succeeded =
+                                prerequisites_of(index).iter().all(|prerequisite| {
+                                    state
+                                        .done
+                                        .get(*prerequisite)
+                                        .map_or(false, |done| *done == Some(true))
+                                });
+                            break (index, prerequisites_succeeded);
+                        }
+                        None if unstarted().next().is_none() => return,
+                        None => guard = changed.wait(guard).unwrap(),
+                    }
+                };
+                guard.started[index] = true;
+                guard.running += 1;
+                drop(guard);
+
+                let result = f(&items[index], prerequisites_succeeded);
+                let success = succeeded(&result);
+                results.lock().unwrap().push((index, result));
+
+                let mut guard = schedule.lock().unwrap();
+                guard.done[index] = Some(success);
+                guard.running -= 1;
+                changed.notify_all();
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Applies `f` to every item on up to `concurrency` threads, keeping the order of `items`.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], concurrency: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                match items.get(index) {
+                    Some(item) => {
+                        let result = f(item);
+                        results.lock().unwrap().push((index, result));
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finishes_prerequisites_first() {
+        let finished = Mutex::new(Vec::new());
+        let results = dependent_map(
+            &[0, 1, 2],
+            &[vec![1], vec![2], vec![]],
+            4,
+            |item, prerequisites_succeeded| {
+                finished.lock().unwrap().push(*item);
+                (*item, prerequisites_succeeded)
+            },
+            |_| true,
+        );
+
+        assert_eq!(finished.into_inner().unwrap(), vec![2, 1, 0]);
+        assert_eq!(results, vec![(0, true), (1, true), (2, true)]);
+    }
+
+    #[test]
+    fn tells_about_failed_prerequisites() {
+        let results = dependent_map(
+            &[0, 1, 2],
+            &[vec![], vec![0], vec![1, 7]],
+            2,
+            |item, prerequisites_succeeded| (*item, prerequisites_succeeded),
+            |(item, _)| *item != 0,
+        );
+
+        assert_eq!(results, vec![(0, true), (1, false), (2, false)]);
+    }
+
+    #[test]
+    fn starts_cycles_as_failed() {
+        let results = dependent_map(
+            &[0, 1],
+            &[vec![1], vec![0]],
+            2,
+            |item, prerequisites_succeeded| (*item, prerequisites_succeeded),
+            |_| true,
+        );
+
+        assert_eq!(results, vec![(0, false), (1, true)]);
+    }
+}
@@ -0,0 +1,86 @@
+//! Release notes saved next to the artifact, to show what's new after an upgrade.
+
+use crate::discovery::{self, VersionSource};
+use crate::options::DownloadOptions;
+use crate::{companion, sibling_path, ResolvedDownload};
+use just_core::manifest::Manifest;
+use just_core::result::BoxedResult;
+use semver::Version;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+struct Release {
+    body: Option<String>,
+}
+
+/// Saves the release notes of the `resolved` version next to `artifact`, from
+/// `options.changelog_url` or else the body of the GitHub release. `None` if there are none.
+pub(crate) fn fetch(
+    manifest: &Manifest,
+    resolved: &ResolvedDownload,
+    artifact: &Path,
+    options: &DownloadOptions,
+) -> BoxedResult<Option<PathBuf>> {
+    use std::fs;
+
+    let version = &resolved.version;
+    if let Some(changelog_url) = &options.changelog_url {
+        let url = companion::url(changelog_url, &resolved.raw_version);
+        return companion::fetch(&options.client(&url)?, artifact, &url, options).map(Some);
+    }
+
+    let repository = match &options.version_source {
+        Some(VersionSource::GitHubTags {
+            repository: Some(repository),
+        }) => Some(repository.clone()),
+        _ => discovery::github_repository(&manifest.download.url),
+    };
+    let notes = match repository {
+        Some(repository) => github_release_notes(&repository, version, options)?,
+        None => None,
+    };
+
+    match notes {
+        Some(notes) => {
+            let path = sibling_path(artifact, ".changelog.md");
+            fs::write(&path, notes)?;
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The body of the release of `version`, tagged with or without a `v` prefix.
+fn github_release_notes(
+    repository: &str,
+    version: &Version,
+    options: &DownloadOptions,
+) -> BoxedResult<Option<String>> {
+    use log::debug;
+    use reqwest::header::{ACCEPT, USER_AGENT};
+    use reqwest::StatusCode;
+
+    for tag in &[format!("v{}", version), version.to_string()] {
+        let url = format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            repository, tag
+        );
+        debug!("Looking up release notes at {}", url);
+
+        let client = options.client(&url)?;
+        let request = client
+            .get(&url)
+            .header(USER_AGENT, "just-download")
+            .header(ACCEPT, "application/vnd.github.v3+json");
+        let response = options.send(&client, request)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            continue;
+        }
+        let release: Release = response.error_for_status()?.json()?;
+
+        return Ok(release.body.filter(|body| !body.trim().is_empty()));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,211 @@
+use crate::error::DownloadError;
+use just_core::result::BoxedResult;
+use serde::de::{self, Deserialize, Deserializer};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+    /// Only accepted with `DownloadOptions::allow_insecure_digests`.
+    Sha1,
+    /// Only accepted with `DownloadOptions::allow_insecure_digests`.
+    Md5,
+}
+
+impl DigestAlgorithm {
+    pub fn is_insecure(self) -> bool {
+        matches!(self, DigestAlgorithm::Sha1 | DigestAlgorithm::Md5)
+    }
+
+    fn digest(self, reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+        use sha2::{Sha256, Sha512};
+
+        match self {
+            DigestAlgorithm::Sha256 => hash::<Sha256>(reader),
+            DigestAlgorithm::Sha512 => hash::<Sha512>(reader),
+            #[cfg(feature = "digests")]
+            DigestAlgorithm::Sha1 => hash::<sha1::Sha1>(reader),
+            #[cfg(feature = "digests")]
+            DigestAlgorithm::Md5 => hash::<md5::Md5>(reader),
+            #[cfg(feature = "digests")]
+            DigestAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(reader, &mut hasher)?;
+
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+            #[cfg(not(feature = "digests"))]
+            _ => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{:?} checksums require the `digests` feature", self),
+            )),
+        }
+    }
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(DigestAlgorithm::Sha256),
+            "sha512" => Ok(DigestAlgorithm::Sha512),
+            "blake3" => Ok(DigestAlgorithm::Blake3),
+            "sha1" => Ok(DigestAlgorithm::Sha1),
+            "md5" => Ok(DigestAlgorithm::Md5),
+            _ => Err(format!("Unknown digest algorithm '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Sha512 => "sha512",
+            DigestAlgorithm::Blake3 => "blake3",
+            DigestAlgorithm::Sha1 => "sha1",
+            DigestAlgorithm::Md5 => "md5",
+        };
+
+        f.write_str(name)
+    }
+}
+
+fn hash<D: digest::Digest>(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The hex digest of the file at `path`.
+pub(crate) fn file_digest(algorithm: DigestAlgorithm, path: &Path) -> io::Result<String> {
+    Ok(to_hex(&algorithm.digest(&mut File::open(path)?)?))
+}
+
+/// An expected digest, written as `<algorithm>:<hex>` (e.g. `sha256:9f86d0...`).
+/// A bare hex digest is taken as sha256.
+/// Deserializes from that notation, e.g. for a `[download.checksums]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: DigestAlgorithm,
+    pub digest: String,
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, digest) = match s.find(':') {
+            Some(i) => (s[..i].parse()?, &s[i + 1..]),
+            None => (DigestAlgorithm::Sha256, s),
+        };
+
+        Ok(Self {
+            algorithm,
+            digest: digest.trim().to_ascii_lowercase(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Checksum {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.digest)
+    }
+}
+
+impl Checksum {
+    /// Checks the file at `path` against this checksum.
+    pub fn verify(&self, path: &Path, allow_insecure: bool) -> BoxedResult<()> {
+        if self.algorithm.is_insecure() && !allow_insecure {
+            return Err(DownloadError::Policy(format!(
+                "Refusing insecure {} checksum without an explicit opt-in",
+                self.algorithm
+            ))
+            .into());
+        }
+
+        let actual = file_digest(self.algorithm, path)?;
+        if actual == self.digest {
+            Ok(())
+        } else {
+            Err(DownloadError::Verification(format!(
+                "Checksum mismatch for {:?}: expected {}, got {}:{}",
+                path, self, self.algorithm, actual
+            ))
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prefixed_checksums() {
+        let checksum: Checksum = "SHA512: ABCdef ".parse().unwrap();
+        assert_eq!(checksum.algorithm, DigestAlgorithm::Sha512);
+        assert_eq!(checksum.digest, "abcdef");
+        assert_eq!(checksum.to_string(), "sha512:abcdef");
+    }
+
+    #[test]
+    fn defaults_to_sha256() {
+        let checksum: Checksum = "ABCDEF".parse().unwrap();
+        assert_eq!(checksum.algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(checksum.digest, "abcdef");
+    }
+
+    #[test]
+    fn rejects_unknown_algorithms() {
+        assert!("crc32:abcdef".parse::<Checksum>().is_err());
+    }
+
+    #[test]
+    fn digests_sha256() {
+        let digest = DigestAlgorithm::Sha256.digest(&mut &b"abc"[..]).unwrap();
+        assert_eq!(
+            to_hex(&digest),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn refuses_insecure_digests_without_opt_in() {
+        let checksum: Checksum = "md5:d41d8cd98f00b204e9800998ecf8427e".parse().unwrap();
+        let e = checksum
+            .verify(Path::new("not-downloaded.zip"), false)
+            .unwrap_err();
+        match e.downcast_ref::<DownloadError>() {
+            Some(DownloadError::Policy(_)) => {}
+            _ => panic!("Unexpected error {}", e),
+        }
+    }
+}
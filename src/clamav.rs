@@ -0,0 +1,131 @@
+//! Scanning artifacts with a local clamd, which needs the `clamav` feature.
+//!
+//! clamd only accepts streams up to its `StreamMaxLength` (25 MB by default), larger
+//! artifacts aren't scanned at all. Raise it in `clamd.conf`, e.g. `StreamMaxLength 1G`,
+//! to scan large downloads, see `ClamAv::too_large`.
+
+use crate::hooks::{Scanner, Verdict};
+use just_core::result::BoxedResult;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The size of the chunks the artifact is streamed to clamd in.
+const CHUNK: usize = 64 * 1024;
+
+/// Where clamd listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClamdAddress {
+    /// A local socket, like `/var/run/clamav/clamd.ctl`.
+    #[cfg(unix)]
+    Socket(std::path::PathBuf),
+    /// A TCP address, like `127.0.0.1:3310`.
+    Tcp(String),
+}
+
+/// What to do with an artifact clamd found something in, or couldn't scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDetection {
+    /// Keep the artifact and report the finding as a warning.
+    Warn,
+    /// Delete the artifact and fail the download.
+    Fail,
+}
+
+/// Streams every artifact to clamd, use it as `DownloadOptions::scanner`.
+#[derive(Debug, Clone)]
+pub struct ClamAv {
+    pub address: ClamdAddress,
+    pub on_detection: OnDetection,
+    /// What to do with an artifact larger than clamd's `StreamMaxLength`, which it refuses
+    /// to scan.
+    pub too_large: OnDetection,
+}
+
+impl ClamAv {
+    /// Fails downloads clamd at `address` finds something in or can't scan for their size.
+    pub fn new(address: ClamdAddress) -> Self {
+        Self {
+            address,
+            on_detection: OnDetection::Fail,
+            too_large: OnDetection::Fail,
+        }
+    }
+
+    fn reply(&self, artifact: &Path) -> BoxedResult<String> {
+        match &self.address {
+            #[cfg(unix)]
+            ClamdAddress::Socket(path) => {
+                instream(std::os::unix::net::UnixStream::connect(path)?, artifact)
+            }
+            ClamdAddress::Tcp(address) => {
+                instream(std::net::TcpStream::connect(address.as_str())?, artifact)
+            }
+        }
+    }
+}
+
+impl Scanner for ClamAv {
+    fn scan(&self, artifact: &Path) -> BoxedResult<Verdict> {
+        use log::debug;
+
+        let reply = self.reply(artifact)?;
+        debug!("clamd scanned {:?}: {}", artifact, reply);
+
+        // The reply is `stream: OK`, `stream: <signature> FOUND` or `<message> ERROR`.
+        let result = reply.trim_start_matches("stream:").trim();
+        if result == "OK" {
+            Ok(Verdict::Clean)
+        } else if result.ends_with(" FOUND") {
+            let finding = format!("clamd found {}", result.trim_end_matches(" FOUND"));
+            Ok(verdict(self.on_detection, finding))
+        } else if result.starts_with("INSTREAM size limit exceeded") {
+            let finding = "it's too large for clamd's StreamMaxLength to be scanned".to_owned();
+            Ok(verdict(self.too_large, finding))
+        } else {
+            Err(format!("clamd could not scan {:?}: {}", artifact, result).into())
+        }
+    }
+}
+
+fn verdict(on_detection: OnDetection, finding: String) -> Verdict {
+    match on_detection {
+        OnDetection::Warn => Verdict::Suspicious(finding),
+        OnDetection::Fail => Verdict::Rejected(finding),
+    }
+}
+
+/// Sends `artifact` with the `INSTREAM` command and returns the reply.
+fn instream<S: Read + Write>(mut stream: S, artifact: &Path) -> BoxedResult<String> {
+    use std::fs::File;
+
+    let mut file = File::open(artifact)?;
+    let mut send = || -> std::io::Result<()> {
+        stream.write_all(b"zINSTREAM\0")?;
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&(n as u32).to_be_bytes())?;
+            stream.write_all(&buf[..n])?;
+        }
+        stream.write_all(&0u32.to_be_bytes())
+    };
+    let sent = send();
+
+    // clamd closes the connection after replying, which it does early when the stream is
+    // too large, so the reply is read even if sending failed.
+    let mut reply = Vec::new();
+    let received = stream.read_to_end(&mut reply);
+    let reply = String::from_utf8_lossy(&reply)
+        .trim_end_matches('\0')
+        .trim()
+        .to_owned();
+
+    match (sent, received) {
+        (Err(e), _) if reply.is_empty() => Err(e.into()),
+        (Ok(()), Err(e)) => Err(e.into()),
+        _ => Ok(reply),
+    }
+}
@@ -0,0 +1,46 @@
+//! Small files published next to an artifact, like SBOMs or signatures.
+
+use crate::options::DownloadOptions;
+use crate::{metadata, versions};
+use just_core::result::BoxedResult;
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+
+/// Fills a companion URL template with the version as it's listed, like download URLs are.
+pub(crate) fn url(template: &str, raw_version: &str) -> String {
+    versions::substitute(template, raw_version)
+}
+
+/// The path next to `artifact` named after the last segment of `url`.
+pub(crate) fn path(artifact: &Path, url: &str) -> BoxedResult<PathBuf> {
+    use reqwest::Url;
+
+    let url = Url::parse(url)?;
+    let file_name = url
+        .path_segments()
+        .and_then(|segments| segments.last())
+        .filter(|segment| !segment.is_empty())
+        .ok_or_else(|| format!("Could not extract a filename from {}", url))?;
+
+    Ok(artifact.with_file_name(file_name))
+}
+
+/// Downloads `url` next to `artifact` and returns where it has been stored.
+pub(crate) fn fetch(
+    client: &Client,
+    artifact: &Path,
+    url: &str,
+    options: &DownloadOptions,
+) -> BoxedResult<PathBuf> {
+    use log::info;
+    use std::fs;
+
+    let path = path(artifact, url)?;
+    info!("Downloading {} into {:?}", url, path);
+
+    let document =
+        metadata::get(client, url, options)?.ok_or_else(|| format!("Nothing found at {}", url))?;
+    fs::write(&path, document)?;
+
+    Ok(path)
+}
@@ -0,0 +1,63 @@
+use crate::sibling_path;
+use reqwest::header::HeaderMap;
+use reqwest::RequestBuilder;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn etag_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".etag")
+}
+
+/// Adds `If-Modified-Since`/`If-None-Match` derived from an already existing artifact.
+pub(crate) fn with_validators(mut request: RequestBuilder, path: &Path) -> RequestBuilder {
+    use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+    use std::fs;
+
+    if !path.exists() {
+        return request;
+    }
+
+    if let Ok(modified) = fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        request = request.header(IF_MODIFIED_SINCE, httpdate::fmt_http_date(modified));
+    }
+    if let Ok(etag) = fs::read_to_string(etag_path(path)) {
+        request = request.header(IF_NONE_MATCH, etag.trim());
+    }
+
+    request
+}
+
+pub(crate) fn etag(headers: &HeaderMap) -> Option<String> {
+    use reqwest::header::ETAG;
+
+    headers
+        .get(ETAG)
+        .and_then(|hv| hv.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Stores the ETag of the artifact at `path`, which must be in place already, so an ETag
+/// never belongs to an artifact the download of which failed.
+pub(crate) fn store_etag(etag: Option<&str>, path: &Path) -> io::Result<()> {
+    use std::fs;
+
+    match etag {
+        Some(etag) => fs::write(etag_path(path), etag),
+        None => match fs::remove_file(etag_path(path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// The strong ETag, or else the Last-Modified date, to send as `If-Range`. Weak ETags
+/// mustn't be used for ranges. Only the segments of a download are requested with it.
+pub(crate) fn range_validator(headers: &HeaderMap) -> Option<String> {
+    use reqwest::header::{ETAG, LAST_MODIFIED};
+
+    let header = |name| headers.get(name).and_then(|hv| hv.to_str().ok());
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_owned)
+}
@@ -0,0 +1,212 @@
+use crate::har::HarRecorder;
+use crate::options::{DownloadOptions, HostOptions, RateUnit, UnitPrefix};
+use just_core::result::BoxedResult;
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Defaults for downloads, read from `~/.config/just/download.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub cache_dir: Option<PathBuf>,
+    pub proxy: Option<String>,
+    pub retries: Option<usize>,
+    /// Parallel downloads of the batch APIs.
+    pub concurrency: Option<usize>,
+    pub max_bytes_per_second: Option<u64>,
+    pub verification: VerificationConfig,
+    pub progress: ProgressConfig,
+    /// `[host."artifacts.corp"]` sections, applied to requests to these hosts.
+    #[serde(rename = "host")]
+    pub hosts: HashMap<String, HostOptions>,
+    /// The region whose mirrors are tried first, e.g. `cn`.
+    pub region: Option<String>,
+    /// The regions of mirror URL templates.
+    pub mirror_regions: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct VerificationConfig {
+    pub require_checksum: Option<bool>,
+    pub allow_insecure_digests: Option<bool>,
+    pub retries: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ProgressConfig {
+    pub template: Option<String>,
+    pub progress_chars: Option<String>,
+    pub spinner_template: Option<String>,
+    /// Redraws per second.
+    pub refresh_rate: Option<u64>,
+    /// Seconds speed and ETA are averaged over.
+    pub speed_window: Option<u64>,
+    /// `bytes` or `bits`.
+    pub rate_unit: Option<RateUnit>,
+    /// `binary` or `decimal`.
+    pub unit_prefix: Option<UnitPrefix>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("just").join("download.toml"))
+    }
+
+    /// Loads the config file, or the defaults if there is none.
+    pub fn load() -> BoxedResult<Self> {
+        match Self::path() {
+            Some(ref path) if path.exists() => Self::from_file(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    #[cfg(feature = "config")]
+    pub fn from_file(path: &Path) -> BoxedResult<Self> {
+        use std::fs;
+
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    #[cfg(not(feature = "config"))]
+    pub fn from_file(path: &Path) -> BoxedResult<Self> {
+        Err(format!("Reading {:?} requires the `config` feature", path).into())
+    }
+}
+
+impl DownloadOptions {
+    /// Options defaulting to `config`, overridden by the environment as `with_env` describes,
+    /// to be overridden programmatically.
+    pub fn from_config(config: &Config) -> Self {
+        let mut options = Self::default();
+        options.cache_dir = config.cache_dir.clone();
+        options.proxy = config.proxy.clone();
+        options.retries = config.retries.unwrap_or(options.retries);
+        options.max_bytes_per_second = config.max_bytes_per_second;
+        options.concurrency = config.concurrency.unwrap_or(options.concurrency);
+
+        let verification = &config.verification;
+        options.require_checksum = verification
+            .require_checksum
+            .unwrap_or(options.require_checksum);
+        options.allow_insecure_digests = verification
+            .allow_insecure_digests
+            .unwrap_or(options.allow_insecure_digests);
+        options.verification_retries = verification.retries.unwrap_or(options.verification_retries);
+
+        let progress = &config.progress;
+        if let Some(template) = &progress.template {
+            options.progress.template = template.clone();
+        }
+        if let Some(progress_chars) = &progress.progress_chars {
+            options.progress.progress_chars = progress_chars.clone();
+        }
+        if let Some(spinner_template) = &progress.spinner_template {
+            options.progress.spinner_template = spinner_template.clone();
+        }
+        options.progress.refresh_rate = progress.refresh_rate;
+        options.progress.speed_window = progress.speed_window.map(Duration::from_secs);
+        options.progress.rate_unit = progress.rate_unit.unwrap_or(options.progress.rate_unit);
+        options.progress.unit_prefix = progress
+            .unit_prefix
+            .unwrap_or(options.progress.unit_prefix);
+        options.hosts = config.hosts.clone();
+        options.region = config.region.clone();
+        options.mirror_regions = config.mirror_regions.clone();
+
+        options.with_env()
+    }
+
+    /// Applies `JUST_DOWNLOAD_*` environment variables on top of these options.
+    ///
+    /// Honored are `PROXY`, `CACHE_DIR`, `REGION`, `OFFLINE`, `MAX_RETRIES`,
+    /// `INSECURE_DIGESTS` and `INSECURE_TLS`; flags are set by `1`, `true` or `yes`.
+    /// `LOG_HTTP` logs the headers of every request, `HAR` records the requests into the
+    /// HAR file at the given path.
+    ///
+    /// `from_config` and `load` apply them already. They overwrite what has been set, so
+    /// this mustn't be called after setting options programmatically.
+    pub fn with_env(mut self) -> Self {
+        if let Some(proxy) = env_var("PROXY") {
+            self.proxy = Some(proxy);
+        }
+        if let Some(cache_dir) = env_var("CACHE_DIR") {
+            self.cache_dir = Some(PathBuf::from(cache_dir));
+        }
+        if let Some(region) = env_var("REGION") {
+            self.region = Some(region);
+        }
+        if let Some(har) = env_var("HAR") {
+            self.har = Some(Arc::new(HarRecorder::new(har)));
+        }
+        if let Some(retries) = env_var("MAX_RETRIES") {
+            match retries.parse() {
+                Ok(retries) => self.retries = retries,
+                Err(_) => warn!("Ignoring invalid JUST_DOWNLOAD_MAX_RETRIES {:?}", retries),
+            }
+        }
+        self.offline = env_flag("OFFLINE").unwrap_or(self.offline);
+        self.allow_insecure_digests =
+            env_flag("INSECURE_DIGESTS").unwrap_or(self.allow_insecure_digests);
+        self.accept_invalid_certs = env_flag("INSECURE_TLS").unwrap_or(self.accept_invalid_certs);
+        self.log_http = env_flag("LOG_HTTP").unwrap_or(self.log_http);
+
+        self
+    }
+
+    /// Options defaulting to the config file, overridden by the environment.
+    pub fn load() -> BoxedResult<Self> {
+        Ok(Self::from_config(&Config::load()?))
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    use std::env;
+
+    env::var(format!("JUST_DOWNLOAD_{}", name))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn env_flag(name: &str) -> Option<bool> {
+    env_var(name).map(|value| match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => true,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn parses_env_flags() {
+        for (value, flag) in &[
+            ("1", true),
+            ("TRUE", true),
+            ("yes", true),
+            ("0", false),
+            ("off", false),
+        ] {
+            env::set_var("JUST_DOWNLOAD_TEST_FLAG", value);
+            assert_eq!(env_flag("TEST_FLAG"), Some(*flag), "{}", value);
+        }
+        env::remove_var("JUST_DOWNLOAD_TEST_FLAG");
+        assert_eq!(env_flag("TEST_FLAG"), None);
+    }
+
+    #[test]
+    fn ignores_empty_env_vars() {
+        env::set_var("JUST_DOWNLOAD_TEST_EMPTY", "");
+        assert_eq!(env_var("TEST_EMPTY"), None);
+        env::set_var("JUST_DOWNLOAD_TEST_EMPTY", "value");
+        assert_eq!(env_var("TEST_EMPTY").as_deref(), Some("value"));
+        env::remove_var("JUST_DOWNLOAD_TEST_EMPTY");
+    }
+}
@@ -0,0 +1,253 @@
+//! Looking up the versions of packages whose manifest doesn't list them.
+
+use crate::checksum;
+use crate::options::DownloadOptions;
+use just_core::manifest::Manifest;
+use just_core::result::BoxedResult;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+const GITHUB_PAGES: usize = 10;
+
+/// Where the versions of a package are looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSource {
+    /// The tags of a GitHub repository like `just-cli/just`, the one the download URL
+    /// points to if not given.
+    GitHubTags { repository: Option<String> },
+    /// A page like an Apache directory listing, with every match of `pattern` being a
+    /// version, or its first capture group if it has one, e.g. `tool-([0-9.]+)\.tar\.gz`.
+    Listing { url: String, pattern: String },
+    /// A JSON document with the array of versions at the JSON pointer `array` (`""` for the
+    /// whole document). Its elements are the versions, or hold them at the pointer `field`,
+    /// e.g. `/version` for Node's `index.json`.
+    Json {
+        url: String,
+        array: String,
+        field: Option<String>,
+    },
+}
+
+/// The versions `options.version_source` knows of, none if there's no source or it failed.
+pub(crate) fn discover(manifest: &Manifest, options: &DownloadOptions) -> Vec<String> {
+    use log::warn;
+
+    let source = match &options.version_source {
+        Some(source) => source,
+        None => return Vec::new(),
+    };
+
+    let versions = match source {
+        VersionSource::GitHubTags { repository } => repository
+            .clone()
+            .or_else(|| github_repository(&manifest.download.url))
+            .ok_or_else(|| format!("No GitHub repository for '{}'", manifest.package.name).into())
+            .and_then(|repository| {
+                cached(&format!("github-{}", repository), options, || {
+                    github_tags(&repository, options)
+                })
+            }),
+        VersionSource::Listing { url, pattern } => {
+            cached(&format!("listing-{}", url), options, || {
+                listing(url, pattern, options)
+            })
+        }
+        VersionSource::Json { url, array, field } => {
+            cached(&format!("json-{}", url), options, || {
+                json(url, array, field.as_deref(), options)
+            })
+        }
+    };
+
+    versions.unwrap_or_else(|e| {
+        warn!(
+            "Could not look up the versions of '{}': {}",
+            manifest.package.name, e
+        );
+        Vec::new()
+    })
+}
+
+/// `owner/repo` of a `https://github.com/owner/repo/...` URL.
+pub(crate) fn github_repository(url: &str) -> Option<String> {
+    use reqwest::Url;
+
+    let url = Url::parse(url).ok()?;
+    if url.host_str()? != "github.com" {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?;
+    let owner = segments.next().filter(|owner| !owner.is_empty())?;
+    let repo = segments.next().filter(|repo| !repo.is_empty())?;
+
+    Some(format!("{}/{}", owner, repo))
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+}
+
+fn github_tags(repository: &str, options: &DownloadOptions) -> BoxedResult<Vec<String>> {
+    use log::debug;
+    use reqwest::header::{ACCEPT, USER_AGENT};
+
+    let mut versions = Vec::new();
+    for page in 1..=GITHUB_PAGES {
+        let url = format!(
+            "https://api.github.com/repos/{}/tags?per_page=100&page={}",
+            repository, page
+        );
+        debug!("Looking up tags at {}", url);
+
+        let client = options.client(&url)?;
+        let request = client
+            .get(&url)
+            .header(USER_AGENT, "just-download")
+            .header(ACCEPT, "application/vnd.github.v3+json");
+        let tags: Vec<Tag> = options.send(&client, request)?.error_for_status()?.json()?;
+        if tags.is_empty() {
+            break;
+        }
+        versions.extend(tags.into_iter().map(|tag| tag.name));
+    }
+
+    // The API lists the newest tags first, but the last listed version is the latest.
+    versions.reverse();
+
+    Ok(versions)
+}
+
+#[cfg(feature = "discovery")]
+fn listing(url: &str, pattern: &str, options: &DownloadOptions) -> BoxedResult<Vec<String>> {
+    use log::debug;
+    use regex::Regex;
+
+    let pattern = Regex::new(pattern)?;
+    debug!("Looking up versions at {}", url);
+    let client = options.client(url)?;
+    let page = options
+        .send(&client, client.get(url))?
+        .error_for_status()?
+        .text()?;
+
+    let mut versions: Vec<String> = Vec::new();
+    for captures in pattern.captures_iter(&page) {
+        let version = captures.get(1).or_else(|| captures.get(0));
+        if let Some(version) = version.map(|version| version.as_str()) {
+            if !versions.iter().any(|listed| listed == version) {
+                versions.push(version.to_owned());
+            }
+        }
+    }
+
+    Ok(versions)
+}
+
+#[cfg(not(feature = "discovery"))]
+fn listing(url: &str, _pattern: &str, _options: &DownloadOptions) -> BoxedResult<Vec<String>> {
+    Err(format!(
+        "Listing the versions at {} requires the `discovery` feature",
+        url
+    )
+    .into())
+}
+
+fn json(
+    url: &str,
+    array: &str,
+    field: Option<&str>,
+    options: &DownloadOptions,
+) -> BoxedResult<Vec<String>> {
+    use log::debug;
+    use serde_json::Value;
+
+    debug!("Looking up versions at {}", url);
+    let client = options.client(url)?;
+    let document: Value = options
+        .send(&client, client.get(url))?
+        .error_for_status()?
+        .json()?;
+    let elements = document
+        .pointer(array)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("No array at {:?} in {}", array, url))?;
+
+    Ok(elements
+        .iter()
+        .filter_map(|element| match field {
+            Some(field) => element.pointer(field),
+            None => Some(element),
+        })
+        .filter_map(Value::as_str)
+        .map(str::to_owned)
+        .collect())
+}
+
+fn cache_path(key: &str) -> Option<PathBuf> {
+    use sha2::{Digest, Sha256};
+
+    let file_name = checksum::to_hex(&Sha256::digest(key.as_bytes()));
+    dirs::cache_dir().map(|cache| {
+        cache
+            .join("just")
+            .join("versions")
+            .join(format!("{}.json", file_name))
+    })
+}
+
+/// The versions stored under `key`, looked up again with `lookup` once they're outdated.
+/// Offline, only the stored versions are used, however old they are.
+fn cached<F>(key: &str, options: &DownloadOptions, lookup: F) -> BoxedResult<Vec<String>>
+where
+    F: FnOnce() -> BoxedResult<Vec<String>>,
+{
+    use log::debug;
+    use std::fs;
+
+    let path = cache_path(key);
+    let ttl = options.version_cache_ttl.unwrap_or(DEFAULT_TTL);
+    let fresh = path
+        .as_ref()
+        .and_then(|path| fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age < ttl)
+        .unwrap_or(false);
+    if fresh || options.offline {
+        let versions = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|json| serde_json::from_slice(&json).ok());
+        match versions {
+            Some(versions) => return Ok(versions),
+            None if options.offline => {
+                return Err(format!(
+                    "Offline, but the versions of {} haven't been listed yet",
+                    key
+                )
+                .into())
+            }
+            None => {}
+        }
+    }
+
+    let versions = lookup()?;
+    if let Some(path) = &path {
+        let stored = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, serde_json::to_vec(&versions)?));
+        if let Err(e) = stored {
+            debug!(
+                "Could not cache the versions of {} in {:?}: {}",
+                key, path, e
+            );
+        }
+    }
+
+    Ok(versions)
+}
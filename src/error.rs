@@ -0,0 +1,251 @@
+use crate::versions::VersionSelector;
+use semver::Version;
+use serde::ser::{Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Serializes as a report of the `message`, the `kind` of failure, the `package`,
+/// `requirement`, `version` and `url` involved, the number of `retries` and the `sources`
+/// of the error as strings.
+#[derive(Debug)]
+pub enum DownloadError {
+    /// Downloading a package failed, even after retrying. Every error of a download is
+    /// returned as this.
+    Failed(Box<Failure>),
+    /// No version of the package matches, or the manifest has no download URL.
+    NotFound(String),
+    /// The artifact doesn't match its checksum or its declared kind.
+    Verification(String),
+    /// The artifact is forbidden by a policy of the `DownloadOptions`.
+    Policy(String),
+    /// The destination exists already and `CollisionPolicy::Error` is in effect.
+    AlreadyExists(PathBuf),
+    /// The artifact at the URL changed while it was downloaded in ranges.
+    Changed(String),
+    /// The deadline of the download passed before it finished.
+    TimedOut,
+    /// The download URL of the manifest can't be used, e.g. because it has a placeholder
+    /// left which isn't substituted.
+    InvalidUrl(String),
+}
+
+impl DownloadError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            DownloadError::Failed(failure) => ErrorCategory::of(&*failure.source),
+            DownloadError::NotFound(_) => ErrorCategory::NotFound,
+            DownloadError::Verification(_) => ErrorCategory::Verification,
+            DownloadError::Policy(_) => ErrorCategory::Policy,
+            DownloadError::AlreadyExists(_) => ErrorCategory::Disk,
+            DownloadError::Changed(_) => ErrorCategory::Network,
+            DownloadError::TimedOut => ErrorCategory::Cancelled,
+            DownloadError::InvalidUrl(_) => ErrorCategory::Other,
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Failed(failure) => {
+                write!(f, "Downloading '{}' ", failure.package)?;
+                match &failure.version {
+                    Some(version) => write!(f, "{}", version)?,
+                    None => write!(f, "{}", failure.requirement)?,
+                }
+                if let Some(url) = &failure.url {
+                    write!(f, " from {}", url)?;
+                }
+                write!(f, " failed: {}", failure.source)
+            }
+            DownloadError::NotFound(message) => write!(f, "Not found: {}", message),
+            DownloadError::Verification(message) => write!(f, "Verification failed: {}", message),
+            DownloadError::Policy(message) => write!(f, "Rejected by policy: {}", message),
+            DownloadError::AlreadyExists(path) => write!(f, "{:?} already exists", path),
+            DownloadError::Changed(url) => write!(f, "{} changed while downloading it", url),
+            DownloadError::TimedOut => {
+                write!(f, "The deadline passed before the download finished")
+            }
+            DownloadError::InvalidUrl(message) => write!(f, "Invalid download URL: {}", message),
+        }
+    }
+}
+
+impl Error for DownloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DownloadError::Failed(failure) => Some(&*failure.source),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for DownloadError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        struct Report<'a> {
+            message: String,
+            kind: ErrorCategory,
+            package: Option<&'a str>,
+            requirement: Option<&'a str>,
+            version: Option<String>,
+            url: Option<&'a str>,
+            retries: usize,
+            sources: Vec<String>,
+        }
+
+        let failure = match self {
+            DownloadError::Failed(failure) => Some(failure),
+            _ => None,
+        };
+        let mut sources = Vec::new();
+        let mut source = self.source();
+        while let Some(e) = source {
+            sources.push(e.to_string());
+            source = e.source();
+        }
+
+        Report {
+            message: self.to_string(),
+            kind: self.category(),
+            package: failure.map(|failure| failure.package.as_str()),
+            requirement: failure.map(|failure| failure.requirement.as_str()),
+            version: failure
+                .and_then(|failure| failure.version.as_ref())
+                .map(ToString::to_string),
+            url: failure.and_then(|failure| failure.url.as_deref()),
+            retries: failure.map(|failure| failure.retries).unwrap_or(0),
+            sources,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// What was being downloaded when a download failed.
+#[derive(Debug)]
+pub struct Failure {
+    pub package: String,
+    /// The version asked for, e.g. `^1.2` or `latest`.
+    pub requirement: String,
+    /// The version it resolved to, if it got that far.
+    pub version: Option<Version>,
+    /// The URL of the last attempt, if it got that far.
+    pub url: Option<String>,
+    /// The attempts made after the first one.
+    pub retries: usize,
+    pub source: Box<dyn Error>,
+}
+
+/// `e` as a `DownloadError::Failed` of `package`, keeping the context it has already.
+pub(crate) fn with_context(
+    e: Box<dyn Error>,
+    package: &str,
+    selector: &VersionSelector,
+    resolved: Option<(&str, &Version)>,
+) -> Box<dyn Error> {
+    let mut failure = match e.downcast::<DownloadError>() {
+        Ok(e) => match *e {
+            DownloadError::Failed(failure) => failure,
+            e => failure(package, selector, e.into()),
+        },
+        Err(e) => failure(package, selector, e),
+    };
+    if let Some((url, version)) = resolved {
+        failure.url.get_or_insert_with(|| url.to_owned());
+        failure.version.get_or_insert_with(|| version.clone());
+    }
+
+    DownloadError::Failed(failure).into()
+}
+
+fn failure(package: &str, selector: &VersionSelector, source: Box<dyn Error>) -> Box<Failure> {
+    Box::new(Failure {
+        package: package.to_owned(),
+        requirement: selector.to_string(),
+        version: None,
+        url: None,
+        retries: 0,
+        source,
+    })
+}
+
+/// What kind of failure an error is. The exit codes are stable, so CLIs can exit with
+/// them directly and scripts can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCategory {
+    /// Anything not covered by another category.
+    Other,
+    /// The connection failed, timed out or the server answered with an error.
+    Network,
+    /// The server or the manifest doesn't have what was asked for.
+    NotFound,
+    /// A checksum, signature or provenance didn't match.
+    Verification,
+    /// The artifact couldn't be read or written locally.
+    Disk,
+    /// The artifact is forbidden by a policy of the `DownloadOptions`.
+    Policy,
+    /// The download was stopped before it finished, e.g. by its deadline.
+    Cancelled,
+}
+
+impl ErrorCategory {
+    /// Categorizes any error a download returned, e.g. by its HTTP status.
+    pub fn of(error: &(dyn Error + 'static)) -> Self {
+        if let Some(e) = error.downcast_ref::<DownloadError>() {
+            e.category()
+        } else if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+            match e.status().map(|status| status.as_u16()) {
+                Some(404) | Some(410) => ErrorCategory::NotFound,
+                _ => ErrorCategory::Network,
+            }
+        } else if let Some(e) = error.downcast_ref::<io::Error>() {
+            match e.kind() {
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::UnexpectedEof => ErrorCategory::Network,
+                io::ErrorKind::Interrupted => ErrorCategory::Cancelled,
+                _ => ErrorCategory::Disk,
+            }
+        } else {
+            ErrorCategory::Other
+        }
+    }
+
+    /// The exit code for the category, `1` for `Other` and `2` to `7` for the rest.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Other => 1,
+            ErrorCategory::Network => 2,
+            ErrorCategory::NotFound => 3,
+            ErrorCategory::Verification => 4,
+            ErrorCategory::Disk => 5,
+            ErrorCategory::Policy => 6,
+            ErrorCategory::Cancelled => 7,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorCategory::Other => "other",
+            ErrorCategory::Network => "network",
+            ErrorCategory::NotFound => "not-found",
+            ErrorCategory::Verification => "verification",
+            ErrorCategory::Disk => "disk",
+            ErrorCategory::Policy => "policy",
+            ErrorCategory::Cancelled => "cancelled",
+        };
+
+        f.write_str(name)
+    }
+}
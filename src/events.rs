@@ -0,0 +1,81 @@
+use semver::Version;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+
+/// What happens during a download, for frontends rendering their own progress.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadEvent {
+    Resolved {
+        package: String,
+        version: Version,
+        url: String,
+    },
+    Started {
+        package: String,
+        url: String,
+        size: Option<u64>,
+    },
+    Progress {
+        package: String,
+        transferred: u64,
+        total: Option<u64>,
+    },
+    MirrorSwitched {
+        package: String,
+        from: String,
+        to: String,
+    },
+    Verified {
+        package: String,
+    },
+    Finished {
+        package: String,
+        size: u64,
+    },
+    Failed {
+        package: String,
+        error: String,
+    },
+    /// The bytes transferred by all downloads of a batch, out of the sizes known so far.
+    BatchProgress {
+        transferred: u64,
+        total: u64,
+    },
+    /// A package of a batch and its dependencies in the batch have been downloaded, so it
+    /// can be installed.
+    Ready {
+        package: String,
+        path: PathBuf,
+    },
+}
+
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: &DownloadEvent);
+}
+
+impl<F: Fn(&DownloadEvent) + Send + Sync> EventListener for F {
+    fn on_event(&self, event: &DownloadEvent) {
+        self(event)
+    }
+}
+
+impl fmt::Debug for dyn EventListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EventListener")
+    }
+}
+
+/// A listener forwarding every event into a channel.
+pub fn channel() -> (Arc<dyn EventListener>, Receiver<DownloadEvent>) {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    let listener = move |event: &DownloadEvent| {
+        if let Ok(sender) = sender.lock() {
+            let _ = sender.send(event.clone());
+        }
+    };
+
+    (Arc::new(listener), receiver)
+}
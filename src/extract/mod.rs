@@ -0,0 +1,353 @@
+use crate::platform;
+use just_core::result::BoxedResult;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+mod sevenz;
+mod tar;
+mod zip;
+
+/// Guards against decompression bombs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Maximum number of bytes an archive may unpack to.
+    pub max_size: Option<u64>,
+    /// Maximum ratio between the unpacked and the packed size.
+    pub max_ratio: Option<u64>,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            max_ratio: Some(1_000),
+        }
+    }
+}
+
+impl ExtractLimits {
+    /// The number of bytes an archive of `packed_size` bytes may unpack to.
+    fn cap(&self, packed_size: u64) -> u64 {
+        let by_ratio = self
+            .max_ratio
+            .map(|ratio| packed_size.saturating_mul(ratio));
+
+        self.max_size
+            .into_iter()
+            .chain(by_ratio)
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+/// Fails reading once more than `limit` bytes have been unpacked.
+struct Bounded<R> {
+    inner: R,
+    limit: u64,
+    read: u64,
+}
+
+impl<R> Bounded<R> {
+    fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Bounded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        if self.read > self.limit {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Archive unpacks to more than {} bytes", self.limit),
+            ))
+        } else {
+            Ok(n)
+        }
+    }
+}
+
+fn escape_error(entry: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Archive entry {:?} escapes the target directory", entry),
+    )
+}
+
+/// Resolves an archive entry below `target`, rejecting absolute paths and `..` components.
+fn entry_path(target: &Path, entry: &Path) -> io::Result<PathBuf> {
+    use std::path::Component;
+
+    let mut path = target.to_owned();
+    for component in entry.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(escape_error(entry)),
+        }
+    }
+
+    Ok(path)
+}
+
+/// Rejects a symlink at `entry` whose `link` target would point outside of the target directory.
+fn check_symlink(entry: &Path, link: &Path) -> io::Result<()> {
+    use std::path::Component;
+
+    let normal = |component: &Component| matches!(component, Component::Normal(_));
+    // The link is relative to the directory containing the entry.
+    let mut depth = entry.components().filter(normal).count() as isize - 1;
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return Err(escape_error(entry)),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    SevenZip,
+    /// A self-extracting executable with an embedded 7z archive, only detected with the
+    /// `sevenz` feature.
+    SevenZipSfx,
+}
+
+impl ArchiveKind {
+    /// Detects the archive format from its magic bytes, falling back to the file extension.
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut magic = Vec::with_capacity(262);
+        File::open(path)?.take(262).read_to_end(&mut magic)?;
+
+        // Without the feature it couldn't be extracted, but still be staged as an executable.
+        #[cfg(feature = "sevenz")]
+        {
+            if magic.starts_with(b"MZ") && sevenz::sfx_offset(path)?.is_some() {
+                return Ok(Some(ArchiveKind::SevenZipSfx));
+            }
+        }
+
+        Ok(Self::from_magic(&magic).or_else(|| Self::from_extension(path)))
+    }
+
+    pub fn is_tar(self) -> bool {
+        matches!(
+            self,
+            ArchiveKind::Tar | ArchiveKind::TarGz | ArchiveKind::TarXz | ArchiveKind::TarZst
+        )
+    }
+
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+            Some(ArchiveKind::Zip)
+        } else if magic.starts_with(&[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c]) {
+            Some(ArchiveKind::SevenZip)
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveKind::TarGz)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(ArchiveKind::TarXz)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveKind::TarZst)
+        } else if magic.len() >= 262 && &magic[257..262] == b"ustar" {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn from_extension(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveKind::TarXz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveKind::TarZst)
+        } else if name.ends_with(".7z") {
+            Some(ArchiveKind::SevenZip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` is a bare executable (ELF, Mach-O or PE) rather than an archive.
+pub fn is_executable(path: &Path) -> io::Result<bool> {
+    const MAGICS: [&[u8]; 6] = [
+        b"\x7fELF",
+        b"MZ",
+        &[0xfe, 0xed, 0xfa, 0xce],
+        &[0xfe, 0xed, 0xfa, 0xcf],
+        &[0xcf, 0xfa, 0xed, 0xfe],
+        &[0xca, 0xfe, 0xba, 0xbe],
+    ];
+
+    let mut magic = Vec::with_capacity(4);
+    File::open(path)?.take(4).read_to_end(&mut magic)?;
+
+    Ok(MAGICS.iter().any(|candidate| magic.starts_with(candidate)))
+}
+
+/// How `place_with` puts an artifact into the target directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Copy,
+    /// A hard link to the downloaded artifact, falling back to a copy where that's not
+    /// possible, e.g. across file systems. Both share their content and permissions.
+    HardLink,
+    /// A copy-on-write clone (`clonefile` on APFS, `FICLONE` on btrfs and XFS), which
+    /// takes no time and space until either file is changed. Falls back to a copy.
+    Clone,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement::Copy
+    }
+}
+
+/// Copies an artifact that isn't unpacked (a bare executable or an installer) into `target`,
+/// renamed to `name` if given.
+pub fn place(artifact: &Path, target: &Path, name: Option<&str>) -> BoxedResult<PathBuf> {
+    place_with(artifact, target, name, Placement::Copy)
+}
+
+/// Like `place`, but linking the artifact into `target` if `placement` asks for it.
+pub fn place_with(
+    artifact: &Path,
+    target: &Path,
+    name: Option<&str>,
+    placement: Placement,
+) -> BoxedResult<PathBuf> {
+    use log::info;
+    use std::ffi::OsStr;
+    use std::fs;
+
+    let file_name = name
+        .map(OsStr::new)
+        .or_else(|| artifact.file_name())
+        .ok_or_else(|| format!("No file name for {:?}", artifact))?;
+    let path = entry_path(target, Path::new(file_name))?;
+    info!("Placing {:?} as {:?}", artifact, path);
+
+    fs::create_dir_all(target)?;
+    platform::materialize(artifact, &path, placement)?;
+
+    Ok(path)
+}
+
+/// Unpacks `archive` into the `target` directory.
+pub fn extract(archive: &Path, target: &Path, limits: &ExtractLimits) -> BoxedResult<()> {
+    use log::info;
+    use std::fs;
+
+    let kind = ArchiveKind::detect(archive)?
+        .ok_or_else(|| format!("Unsupported archive format: {:?}", archive))?;
+    info!("Extracting {:?} into {:?}", archive, target);
+
+    let cap = limits.cap(fs::metadata(archive)?.len());
+
+    fs::create_dir_all(target)?;
+    match kind {
+        ArchiveKind::Zip => zip::extract(archive, target, cap),
+        kind if kind.is_tar() => tar::unpack(File::open(archive)?, kind, target, cap),
+        ArchiveKind::SevenZip => sevenz::extract(archive, 0, target, cap),
+        ArchiveKind::SevenZipSfx => {
+            let offset = sevenz::sfx_offset(archive)?
+                .ok_or_else(|| format!("No embedded 7z archive in {:?}", archive))?;
+            sevenz::extract(archive, offset, target, cap)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Unpacks a tarball while it is being read, e.g. straight from the response body.
+#[cfg(feature = "blocking")]
+pub(crate) fn unpack_tar<R: Read>(
+    reader: R,
+    kind: ArchiveKind,
+    target: &Path,
+    packed_size: u64,
+    limits: &ExtractLimits,
+) -> BoxedResult<()> {
+    use std::fs;
+
+    fs::create_dir_all(target)?;
+    tar::unpack(reader, kind, target, limits.cap(packed_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_entries_to_the_target() {
+        let path = entry_path(Path::new("target"), Path::new("./bin/./tool")).unwrap();
+        assert_eq!(path, Path::new("target").join("bin").join("tool"));
+    }
+
+    #[test]
+    fn rejects_entries_escaping_the_target() {
+        for entry in &["../tool", "bin/../../tool", "bin/..", "/etc/passwd"] {
+            let error = entry_path(Path::new("target"), Path::new(entry)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", entry);
+        }
+    }
+
+    #[test]
+    fn accepts_symlinks_within_the_target() {
+        for (entry, link) in &[
+            ("bin/tool", "tool-1.0"),
+            ("bin/tool", "./tool-1.0"),
+            ("bin/tool", "../lib/tool"),
+            ("a/b/tool", "../c/../../d"),
+        ] {
+            assert!(
+                check_symlink(Path::new(entry), Path::new(link)).is_ok(),
+                "{} -> {}",
+                entry,
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_symlinks_escaping_the_target() {
+        for (entry, link) in &[
+            ("tool", "../tool"),
+            ("bin/tool", "../../tool"),
+            ("bin/tool", "lib/../../../tool"),
+            ("bin/tool", "/etc/passwd"),
+        ] {
+            let error = check_symlink(Path::new(entry), Path::new(link)).unwrap_err();
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::InvalidData,
+                "{} -> {}",
+                entry,
+                link
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use just_core::result::BoxedResult;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const SIGNATURE: [u8; 6] = [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c];
+const SFX_SEARCH_LIMIT: u64 = 4 * 1024 * 1024;
+const PE_HEADERS_LIMIT: u64 = 64 * 1024;
+
+/// Finds the embedded 7z archive of a self-extracting executable. The archive is appended
+/// to the PE image (its overlay), a signature inside the image, like in `7za.exe`, is not.
+pub(super) fn sfx_offset(path: &Path) -> io::Result<Option<u64>> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = File::open(path)?;
+    let mut headers = Vec::new();
+    file.by_ref()
+        .take(PE_HEADERS_LIMIT)
+        .read_to_end(&mut headers)?;
+    let overlay = match overlay_offset(&headers) {
+        Some(overlay) => overlay,
+        None => return Ok(None),
+    };
+
+    let mut appended = Vec::new();
+    file.seek(SeekFrom::Start(overlay))?;
+    file.take(SFX_SEARCH_LIMIT).read_to_end(&mut appended)?;
+
+    // The signature is followed by the major format version, which is always 0.
+    Ok(appended
+        .windows(SIGNATURE.len() + 1)
+        .position(|window| {
+            window[..SIGNATURE.len()] == SIGNATURE[..] && window[SIGNATURE.len()] == 0
+        })
+        .map(|position| overlay + position as u64))
+}
+
+/// Where the data appended to the PE image in `headers` starts, after its last section.
+fn overlay_offset(headers: &[u8]) -> Option<u64> {
+    let u16_at = |offset: usize| {
+        let bytes = headers.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    let u32_at = |offset: usize| {
+        let bytes = headers.get(offset..offset + 4)?;
+        Some(u64::from(u32::from_le_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ])))
+    };
+
+    let pe = u32_at(0x3c)? as usize;
+    if headers.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let sections = u16_at(pe + 6)?;
+    let section_table = pe + 24 + u16_at(pe + 20)?;
+
+    (0..sections)
+        .map(|i| {
+            let section = section_table + i * 40;
+            Some(u32_at(section + 20)? + u32_at(section + 16)?)
+        })
+        .collect::<Option<Vec<u64>>>()?
+        .into_iter()
+        .max()
+}
+
+/// Unpacks the 7z archive at `offset` of `archive` entry by entry, like `zip::extract`.
+/// Symlinks are unpacked as the files sevenz-rust reads them as, holding the link target.
+#[cfg(feature = "sevenz")]
+pub(super) fn extract(archive: &Path, offset: u64, target: &Path, limit: u64) -> BoxedResult<()> {
+    use sevenz_rust::{Password, SevenZReader};
+
+    let inner = File::open(archive)?;
+    let len = inner.metadata()?.len().saturating_sub(offset);
+    let mut reader = SevenZReader::new(Embedded { inner, offset }, len, Password::empty())?;
+
+    let mut remaining = limit;
+    let mut failure = None;
+    reader.for_each_entries(|entry, source| {
+        // Stops at the first entry failing, keeping its error over sevenz-rust's.
+        match unpack_entry(entry, source, target, &mut remaining, limit) {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                failure = Some(e);
+                Ok(false)
+            }
+        }
+    })?;
+
+    failure.map_or(Ok(()), Err)
+}
+
+/// Unpacks `entry` below `target`, failing once the archive unpacks to more than `limit` bytes.
+#[cfg(feature = "sevenz")]
+fn unpack_entry(
+    entry: &sevenz_rust::SevenZArchiveEntry,
+    source: &mut dyn Read,
+    target: &Path,
+    remaining: &mut u64,
+    limit: u64,
+) -> BoxedResult<()> {
+    use super::{entry_path, Bounded};
+    use std::fs;
+    use std::io::copy;
+
+    let path = entry_path(target, Path::new(entry.name()))?;
+    if entry.size() > *remaining {
+        return Err(format!("Archive unpacks to more than {} bytes", limit).into());
+    }
+
+    if entry.is_directory() {
+        fs::create_dir_all(&path)?;
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut source = Bounded::new(source, *remaining);
+    *remaining -= copy(&mut source, &mut File::create(&path)?)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sevenz"))]
+pub(super) fn extract(
+    archive: &Path,
+    _offset: u64,
+    _target: &Path,
+    _limit: u64,
+) -> BoxedResult<()> {
+    Err(format!("Extracting {:?} requires the `sevenz` feature", archive).into())
+}
+
+/// Presents an archive that starts at `offset` of `inner` as if it started at 0.
+#[cfg(feature = "sevenz")]
+struct Embedded {
+    inner: File,
+    offset: u64,
+}
+
+#[cfg(feature = "sevenz")]
+impl Read for Embedded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(feature = "sevenz")]
+impl io::Seek for Embedded {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        use std::io::Seek;
+
+        let pos = match pos {
+            io::SeekFrom::Start(n) => io::SeekFrom::Start(n + self.offset),
+            relative => relative,
+        };
+
+        self.inner.seek(pos).map(|n| n.saturating_sub(self.offset))
+    }
+}
@@ -0,0 +1,68 @@
+use super::ArchiveKind;
+#[cfg(feature = "archives")]
+use super::{check_symlink, entry_path, Bounded};
+use just_core::result::BoxedResult;
+#[cfg(feature = "archives")]
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+#[cfg(feature = "archives")]
+fn decoder<'a, R: Read + 'a>(reader: R, kind: ArchiveKind) -> io::Result<Box<dyn Read + 'a>> {
+    use flate2::read::GzDecoder;
+    use xz2::read::XzDecoder;
+
+    Ok(match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(reader)),
+        ArchiveKind::TarXz => Box::new(XzDecoder::new(reader)),
+        ArchiveKind::TarZst => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        _ => Box::new(reader),
+    })
+}
+
+/// Unpacks a (possibly compressed) tarball read from `reader` into `target`.
+#[cfg(feature = "archives")]
+pub(super) fn unpack<R: Read>(
+    reader: R,
+    kind: ArchiveKind,
+    target: &Path,
+    limit: u64,
+) -> BoxedResult<()> {
+    use ::tar::Archive;
+
+    let mut archive = Archive::new(Bounded::new(decoder(reader, kind)?, limit));
+    archive.set_preserve_permissions(true);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        entry_path(target, &path)?;
+
+        if let Some(link) = entry.link_name()?.map(|link| link.into_owned()) {
+            if entry.header().entry_type().is_hard_link() {
+                // Hard links are relative to the archive root.
+                entry_path(target, &link)?;
+            } else {
+                check_symlink(&path, &link)?;
+            }
+        }
+
+        entry.unpack_in(target)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+pub(super) fn unpack<R: Read>(
+    _reader: R,
+    kind: ArchiveKind,
+    _target: &Path,
+    _limit: u64,
+) -> BoxedResult<()> {
+    Err(format!(
+        "Unpacking {:?} archives requires the `archives` feature",
+        kind
+    )
+    .into())
+}
@@ -0,0 +1,50 @@
+#[cfg(feature = "archives")]
+use super::{entry_path, Bounded};
+use just_core::result::BoxedResult;
+use std::path::Path;
+
+#[cfg(feature = "archives")]
+pub(super) fn extract(archive: &Path, target: &Path, limit: u64) -> BoxedResult<()> {
+    use ::zip::ZipArchive;
+    use std::fs::{self, File};
+    use std::io::copy;
+
+    let mut remaining = limit;
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let path = entry_path(target, Path::new(entry.name()))?;
+
+        if entry.size() > remaining {
+            return Err(format!("Archive unpacks to more than {} bytes", limit).into());
+        }
+
+        if entry.name().ends_with('/') {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut source = Bounded::new(&mut entry, remaining);
+        remaining -= copy(&mut source, &mut File::create(&path)?)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            // Without the setuid, setgid and sticky bits an archive could ask for.
+            if let Some(mode) = entry.unix_mode() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+pub(super) fn extract(archive: &Path, _target: &Path, _limit: u64) -> BoxedResult<()> {
+    Err(format!("Extracting {:?} requires the `archives` feature", archive).into())
+}
@@ -0,0 +1,274 @@
+//! File names for artifacts, from their URL or the server's `Content-Disposition`.
+
+#[cfg(feature = "blocking")]
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION};
+#[cfg(feature = "blocking")]
+use std::path::Path;
+
+/// The file name `encoded` in a URL stands for, e.g. `my tool.zip` for `my%20tool.zip`.
+/// A `+` is kept, it only means a space in query strings. Encoded slashes are replaced,
+/// so they can't reach into another directory.
+pub(crate) fn percent_decoded(encoded: &str) -> String {
+    String::from_utf8_lossy(&percent_decoded_bytes(encoded)).into_owned()
+}
+
+/// `name` as a single path component, e.g. of a package name that becomes a directory.
+/// Slashes are replaced like encoded ones, and an empty name, `.` and `..` by underscores.
+pub(crate) fn path_component(name: &str) -> String {
+    let name = name.replace(&['/', '\\'][..], "_");
+    if matches!(name.as_str(), "" | "." | "..") {
+        "_".repeat(name.len() + 1)
+    } else {
+        name
+    }
+}
+
+fn percent_decoded_bytes(encoded: &str) -> Vec<u8> {
+    fn hex(digit: u8) -> Option<u8> {
+        (digit as char).to_digit(16).map(|value| value as u8)
+    }
+
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 3) {
+            Some([b'%', high, low]) => hex(*high).and_then(|high| Some(high << 4 | hex(*low)?)),
+            _ => None,
+        };
+        match escaped {
+            Some(b'/') | Some(b'\\') => {
+                decoded.push(b'_');
+                i += 3;
+            }
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+/// The file name the server suggests with `Content-Disposition`, preferring the RFC 5987
+/// `filename*` over the plain `filename`. Only the last component of the name is used.
+#[cfg(feature = "blocking")]
+pub(crate) fn from_disposition(headers: &HeaderMap) -> Option<String> {
+    // Servers send UTF-8 in plain `filename`s as well, which isn't valid for `to_str`.
+    let value = String::from_utf8_lossy(headers.get(CONTENT_DISPOSITION)?.as_bytes()).into_owned();
+    let parameters = parameters(&value);
+    let parameter = |name: &str| {
+        parameters
+            .iter()
+            .find(|(parameter, _)| parameter.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let name = parameter("filename*")
+        .and_then(extended_value)
+        .or_else(|| parameter("filename").map(str::to_owned))?;
+    let name = name.replace('\\', "/");
+    let name = Path::new(&name).file_name()?.to_string_lossy().into_owned();
+
+    Some(name).filter(|name| !name.trim().is_empty())
+}
+
+/// The `name=value` parameters after the disposition type, with quoted values unescaped.
+#[cfg(feature = "blocking")]
+fn parameters(value: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut chars = value.chars().peekable();
+    // Skips the disposition type, e.g. `attachment`.
+    while chars.next().map_or(false, |c| c != ';') {}
+
+    loop {
+        let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if name.is_empty() {
+            break;
+        }
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+            while chars.next().map_or(false, |c| c != ';') {}
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ';').collect();
+        }
+
+        parameters.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    parameters
+}
+
+/// Decodes an RFC 5987 value like `UTF-8''na%C3%AFve.zip`.
+#[cfg(feature = "blocking")]
+fn extended_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes = percent_decoded_bytes(encoded);
+
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("ISO-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn disposition(value: &[u8]) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_bytes(value).unwrap());
+        from_disposition(&headers)
+    }
+
+    #[test]
+    fn percent_decodes() {
+        assert_eq!(percent_decoded("my%20tool.zip"), "my tool.zip");
+        assert_eq!(percent_decoded("c%2B%2b+1.zip"), "c+++1.zip");
+    }
+
+    #[test]
+    fn replaces_encoded_slashes() {
+        assert_eq!(
+            percent_decoded("..%2F..%2fetc%2Fpasswd"),
+            ".._.._etc_passwd"
+        );
+        assert_eq!(percent_decoded("..%5C..%5cevil.exe"), ".._.._evil.exe");
+    }
+
+    #[test]
+    fn makes_single_path_components() {
+        assert_eq!(path_component("tool"), "tool");
+        assert_eq!(path_component("@scope/tool"), "@scope_tool");
+        assert_eq!(path_component("..\\evil"), ".._evil");
+        assert_eq!(path_component(".."), "___");
+        assert_eq!(path_component(""), "_");
+    }
+
+    #[test]
+    fn keeps_malformed_escapes() {
+        assert_eq!(percent_decoded("100%"), "100%");
+        assert_eq!(percent_decoded("%4"), "%4");
+        assert_eq!(percent_decoded("%zz.zip"), "%zz.zip");
+        assert_eq!(percent_decoded("%%41"), "%A");
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        assert_eq!(percent_decoded("%FF.zip"), "\u{FFFD}.zip");
+    }
+
+    #[test]
+    fn reads_plain_filenames() {
+        assert_eq!(
+            disposition(b"attachment; filename=tool.zip").as_deref(),
+            Some("tool.zip")
+        );
+        assert_eq!(
+            disposition(b"attachment;filename=\"tool.zip\"; size=3").as_deref(),
+            Some("tool.zip")
+        );
+    }
+
+    #[test]
+    fn unescapes_quoted_filenames() {
+        assert_eq!(
+            disposition(br#"attachment; filename="a \"b\".zip""#).as_deref(),
+            Some("a \"b\".zip")
+        );
+        assert_eq!(
+            disposition(br#"attachment; filename="a;b.zip"; size=3"#).as_deref(),
+            Some("a;b.zip")
+        );
+    }
+
+    #[test]
+    fn prefers_extended_filenames() {
+        assert_eq!(
+            disposition(b"attachment; filename=\"fallback.zip\"; filename*=UTF-8''na%C3%AFve.zip")
+                .as_deref(),
+            Some("na\u{EF}ve.zip")
+        );
+        assert_eq!(
+            disposition(b"attachment; FILENAME*=utf-8''tool.zip").as_deref(),
+            Some("tool.zip")
+        );
+    }
+
+    #[test]
+    fn decodes_latin1_filenames() {
+        assert_eq!(
+            disposition(b"attachment; filename*=ISO-8859-1''caf%E9.zip").as_deref(),
+            Some("caf\u{E9}.zip")
+        );
+    }
+
+    #[test]
+    fn falls_back_on_undecodable_extended_filenames() {
+        assert_eq!(
+            disposition(b"attachment; filename=plain.zip; filename*=Shift_JIS''%82%A0.zip")
+                .as_deref(),
+            Some("plain.zip")
+        );
+        assert_eq!(
+            disposition(b"attachment; filename=plain.zip; filename*=UTF-8''%FF.zip").as_deref(),
+            Some("plain.zip")
+        );
+        assert_eq!(disposition(b"attachment; filename*=UTF-8''%FF.zip"), None);
+        assert_eq!(disposition(b"attachment; filename*=tool.zip"), None);
+    }
+
+    #[test]
+    fn replaces_non_utf8_plain_filenames() {
+        assert_eq!(
+            disposition(b"attachment; filename=\"caf\xE9.zip\"").as_deref(),
+            Some("caf\u{FFFD}.zip")
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_last_component() {
+        assert_eq!(
+            disposition(b"attachment; filename=\"../../etc/passwd\"").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            disposition(b"attachment; filename=..\\..\\evil.exe").as_deref(),
+            Some("evil.exe")
+        );
+        assert_eq!(
+            disposition(b"attachment; filename*=UTF-8''..%2F..%2Fevil.exe").as_deref(),
+            Some(".._.._evil.exe")
+        );
+    }
+
+    #[test]
+    fn rejects_names_without_a_file() {
+        assert_eq!(disposition(b"attachment; filename=\"..\""), None);
+        assert_eq!(disposition(b"attachment; filename=/"), None);
+        assert_eq!(disposition(b"attachment; filename=\"  \""), None);
+        assert_eq!(disposition(b"attachment"), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! Callbacks around every download, e.g. for approval prompts or uploading the artifact to
+//! an internal mirror.
+
+use crate::DownloadInfo;
+use just_core::result::BoxedResult;
+use semver::Version;
+use std::fmt;
+use std::path::Path;
+
+/// A resolved download which is about to start.
+#[derive(Debug)]
+pub struct PendingDownload<'a> {
+    pub package: &'a str,
+    pub version: &'a Version,
+    pub url: &'a str,
+    pub destination: &'a Path,
+}
+
+/// Called before the transfer starts. Returning an error cancels the download.
+pub trait BeforeDownload: Send + Sync {
+    fn before_download(&self, download: &PendingDownload) -> BoxedResult<()>;
+}
+
+impl<F: Fn(&PendingDownload) -> BoxedResult<()> + Send + Sync> BeforeDownload for F {
+    fn before_download(&self, download: &PendingDownload) -> BoxedResult<()> {
+        self(download)
+    }
+}
+
+impl fmt::Debug for dyn BeforeDownload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BeforeDownload")
+    }
+}
+
+/// Called with the outcome of every download.
+pub trait AfterDownload: Send + Sync {
+    fn after_download(&self, package: &str, result: &BoxedResult<DownloadInfo>);
+}
+
+impl<F: Fn(&str, &BoxedResult<DownloadInfo>) + Send + Sync> AfterDownload for F {
+    fn after_download(&self, package: &str, result: &BoxedResult<DownloadInfo>) {
+        self(package, result)
+    }
+}
+
+impl fmt::Debug for dyn AfterDownload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AfterDownload")
+    }
+}
+
+/// The outcome of scanning an artifact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    /// The artifact is kept, but the reason is reported as a warning.
+    Suspicious(String),
+    /// The artifact must not be used, for the given reason.
+    Rejected(String),
+}
+
+/// Looks at the downloaded artifact before it's moved into place, e.g. a malware scanner.
+/// A rejected artifact is deleted.
+pub trait Scanner: Send + Sync {
+    fn scan(&self, artifact: &Path) -> BoxedResult<Verdict>;
+}
+
+impl<F: Fn(&Path) -> BoxedResult<Verdict> + Send + Sync> Scanner for F {
+    fn scan(&self, artifact: &Path) -> BoxedResult<Verdict> {
+        self(artifact)
+    }
+}
+
+impl fmt::Debug for dyn Scanner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Scanner")
+    }
+}
@@ -0,0 +1,108 @@
+use crate::options::DownloadOptions;
+use just_core::result::BoxedResult;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Response;
+
+const MAX_HOPS: usize = 3;
+
+pub(crate) fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|hv| hv.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase().starts_with("text/html"))
+        .unwrap_or(false)
+}
+
+/// Follows "your download will start shortly" pages (as served by SourceForge and
+/// similar mirror networks) until the actual artifact is reached.
+pub(crate) fn follow(mut response: Response, options: &DownloadOptions) -> BoxedResult<Response> {
+    use log::debug;
+
+    for _ in 0..MAX_HOPS {
+        // Error pages are left to the status check of the caller.
+        if !response.status().is_success() || !is_html(&response) {
+            return Ok(response);
+        }
+
+        let page_url = response.url().clone();
+        let page = response.text()?;
+        let link = find_artifact_link(&page).ok_or_else(|| {
+            format!(
+                "Received an HTML page instead of an artifact from {}",
+                page_url
+            )
+        })?;
+        let artifact_url = page_url.join(&link)?;
+        debug!("Following interstitial {} to {}", page_url, artifact_url);
+
+        // The link may lead to another host, which mustn't get the credentials of this one.
+        let client = options.client(artifact_url.as_str())?;
+        response = options.send(&client, client.get(artifact_url))?;
+    }
+
+    if is_html(&response) {
+        Err(format!("Gave up following interstitial pages at {}", response.url()).into())
+    } else {
+        Ok(response)
+    }
+}
+
+fn find_artifact_link(page: &str) -> Option<String> {
+    meta_refresh_url(page)
+        .or_else(|| href_containing(page, "use_mirror="))
+        .map(|link| link.replace("&amp;", "&"))
+}
+
+fn meta_refresh_url(page: &str) -> Option<String> {
+    let lower = page.to_ascii_lowercase();
+    let meta = lower.find("http-equiv=\"refresh\"")?;
+    let start = meta + lower[meta..].find("url=")? + "url=".len();
+    let end = start + lower[start..].find(|c| c == '"' || c == '\'')?;
+
+    Some(page[start..end].trim().to_owned())
+}
+
+fn href_containing(page: &str, needle: &str) -> Option<String> {
+    page.split("href=")
+        .skip(1)
+        .filter_map(|attribute| {
+            let quote = attribute
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')?;
+            attribute[1..].split(quote).next()
+        })
+        .find(|link| link.contains(needle))
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_meta_refreshes() {
+        let page = r#"<META HTTP-EQUIV="refresh" content="5; URL=https://downloads.example.com/tool.zip?ts=1&amp;r=2">"#;
+        assert_eq!(
+            find_artifact_link(page).as_deref(),
+            Some("https://downloads.example.com/tool.zip?ts=1&r=2")
+        );
+    }
+
+    #[test]
+    fn finds_mirror_links() {
+        let page = r#"<a href="/projects/tool">Tool</a>
+            <a href='/projects/tool/files/tool.zip/download?use_mirror=autoselect&amp;ts=1'>"#;
+        assert_eq!(
+            find_artifact_link(page).as_deref(),
+            Some("/projects/tool/files/tool.zip/download?use_mirror=autoselect&ts=1")
+        );
+    }
+
+    #[test]
+    fn ignores_pages_without_links() {
+        assert_eq!(find_artifact_link("<p>Not found</p>"), None);
+        assert_eq!(find_artifact_link("<a href=/unquoted?use_mirror=x>"), None);
+    }
+}
@@ -0,0 +1,106 @@
+use just_core::result::BoxedResult;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A signing key trusted for the packages of one publisher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedKey {
+    pub name: String,
+    /// The public key as published, e.g. a minisign or an armored OpenPGP key.
+    pub key: Vec<u8>,
+}
+
+/// Trusted signing keys, stored as `<root>/<publisher>/<name>.pub`.
+/// Revoked keys are moved to `<root>/<publisher>/revoked` so they can't be re-added by accident.
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    root: PathBuf,
+}
+
+fn checked(component: &str) -> BoxedResult<&str> {
+    let valid = !component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains(|c| c == '/' || c == '\\');
+
+    if valid {
+        Ok(component)
+    } else {
+        Err(format!("Invalid key store name '{}'", component).into())
+    }
+}
+
+impl KeyStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    fn key_path(&self, publisher: &str, name: &str) -> BoxedResult<PathBuf> {
+        Ok(self
+            .root
+            .join(checked(publisher)?)
+            .join(format!("{}.pub", checked(name)?)))
+    }
+
+    fn revoked_path(&self, publisher: &str, name: &str) -> BoxedResult<PathBuf> {
+        Ok(self
+            .root
+            .join(checked(publisher)?)
+            .join("revoked")
+            .join(format!("{}.pub", checked(name)?)))
+    }
+
+    pub fn add(&self, publisher: &str, name: &str, key: &[u8]) -> BoxedResult<()> {
+        if self.revoked_path(publisher, name)?.exists() {
+            return Err(format!("Key '{}' of '{}' has been revoked", name, publisher).into());
+        }
+
+        let path = self.key_path(publisher, name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, key)?;
+
+        Ok(())
+    }
+
+    pub fn list(&self, publisher: &str) -> BoxedResult<Vec<TrustedKey>> {
+        let dir = self.root.join(checked(publisher)?);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "pub")
+            {
+                continue;
+            }
+
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                keys.push(TrustedKey {
+                    name: name.to_owned(),
+                    key: fs::read(&path)?,
+                });
+            }
+        }
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(keys)
+    }
+
+    pub fn revoke(&self, publisher: &str, name: &str) -> BoxedResult<()> {
+        let revoked = self.revoked_path(publisher, name)?;
+        if let Some(parent) = revoked.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.key_path(publisher, name)?, revoked)?;
+
+        Ok(())
+    }
+}
@@ -1,16 +1,194 @@
-use indicatif::ProgressBar;
+//! Downloads the artifacts of just packages.
+//!
+//! Cargo features:
+//! - `blocking` (default): `download`, `probe`, `download_and_extract` and the batches of
+//!   `batch`, on reqwest's blocking client.
+//! - `async`: `r#async::download_with_options`, a future on reqwest's async client.
+//! - `progress` (default): draws a progress bar with indicatif.
+//! - `native-tls` (default): HTTPS through the TLS stack of the platform.
+//! - `rustls`: HTTPS through rustls instead, for builds without OpenSSL. With both TLS
+//!   features the platform's TLS is used; with neither, only plain HTTP works.
+//! - `archives` (default): unpacks zip and (compressed) tar archives.
+//! - `digests` (default): SHA-1, MD5 and BLAKE3 checksums besides SHA-256 and SHA-512.
+//! - `signatures` (default): minisign signatures and signed provenance attestations.
+//! - `discovery` (default): versions listed by a pattern over a web page.
+//! - `config` (default): reads `Config` from its TOML file.
+//! - `locking` (default): locks destinations against concurrent `just` processes.
+//! - `telemetry` (default): pseudonymizes the hosts reported to a `TelemetrySink`.
+//! - `mmap`: writes segmented downloads through a memory map.
+//! - `clamav`: scans artifacts with a local clamd, see `clamav::ClamAv`.
+//! - `sevenz`, `tuf`, `openpgp` and `tracing`: 7z archives, TUF metadata, OpenPGP signatures
+//!   and tracing spans.
+//!
+//! `--no-default-features --features blocking,rustls` leaves plain HTTPS downloads reported
+//! through `EventListener`, for embedding into binaries where size matters.
+//!
+//! `blocking` and `async` share the resolution, destinations, checksums and the finishing of
+//! artifacts. reqwest 0.9 always builds its blocking client on top of the async one, so an
+//! `async` only build leaves out the blocking API of this crate, not reqwest's. At least one
+//! of them has to be enabled.
+
+#[cfg(not(any(feature = "blocking", feature = "async")))]
+compile_error!("just-download needs the `blocking` or the `async` feature");
+
+#[cfg(feature = "blocking")]
+use crate::progress::ProgressBar;
+#[cfg(feature = "blocking")]
+use crate::speed::Throughput;
 use just_core::manifest::{Manifest, Package};
 use just_core::result::BoxedResult;
-use semver::{Version, VersionReq};
+use reqwest::header::HeaderMap;
+#[cfg(feature = "blocking")]
+use reqwest::Client;
+use semver::Version;
+#[cfg(feature = "blocking")]
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+#[cfg(feature = "blocking")]
+use std::time::Instant;
+use std::time::SystemTime;
+
+pub mod artifact;
+#[cfg(feature = "async")]
+pub mod r#async;
+#[cfg(feature = "blocking")]
+mod bandwidth;
+#[cfg(feature = "blocking")]
+pub mod batch;
+#[cfg(feature = "blocking")]
+mod changelog;
+pub mod checksum;
+#[cfg(feature = "clamav")]
+pub mod clamav;
+#[cfg(feature = "blocking")]
+mod companion;
+#[cfg(feature = "blocking")]
+mod conditional;
+pub mod config;
+pub mod discovery;
+mod error;
+pub mod events;
+pub mod extract;
+mod filename;
+mod har;
+pub mod hooks;
+#[cfg(feature = "blocking")]
+mod interstitial;
+pub mod keys;
+mod license;
+#[cfg(feature = "blocking")]
+mod lock;
+#[cfg(feature = "blocking")]
+mod metadata;
+mod metrics;
+mod middleware;
+#[cfg(feature = "blocking")]
+mod mirrors;
+mod options;
+mod platform;
+#[cfg(feature = "blocking")]
+mod progress;
+pub mod provenance;
+#[cfg(feature = "blocking")]
+mod retry_budget;
+#[cfg(feature = "blocking")]
+mod segmented;
+mod signature;
+#[cfg(feature = "blocking")]
+mod slots;
+#[cfg(feature = "blocking")]
+mod speed;
+mod telemetry;
+#[cfg(feature = "tuf")]
+pub mod tuf;
+pub mod versions;
+mod warning;
+#[cfg(feature = "blocking")]
+mod wayback;
+
+pub use artifact::{ArtifactKind, InstallerKind};
+#[cfg(feature = "blocking")]
+pub use bandwidth::BandwidthLimiter;
+#[cfg(feature = "blocking")]
+pub use batch::{estimate, resolve_all, BatchReport, DownloadManager, PackageSize};
+#[cfg(feature = "blocking")]
+pub use batch::{resolve_all_with_options, Resolution, ResolutionReport, SizeEstimate};
+pub use checksum::{Checksum, DigestAlgorithm};
+pub use config::Config;
+pub use discovery::VersionSource;
+pub use error::{DownloadError, ErrorCategory, Failure};
+pub use events::{DownloadEvent, EventListener};
+pub use har::HarRecorder;
+pub use hooks::{AfterDownload, BeforeDownload, PendingDownload, Scanner, Verdict};
+pub use license::LicenseAcceptance;
+pub use metrics::{MetricsSink, Phase};
+pub use middleware::Middleware;
+pub use options::{CollisionPolicy, VersionedDestination};
+pub use options::{DownloadOptions, HostOptions, ProgressAppearance, Quarantine};
+pub use options::{RateUnit, UnitPrefix};
+pub use provenance::{Provenance, ProvenancePolicy};
+#[cfg(feature = "blocking")]
+pub use retry_budget::RetryBudget;
+pub use signature::SignaturePolicy;
+#[cfg(feature = "blocking")]
+pub use slots::{Priority, TransferSlots};
+pub use telemetry::TelemetrySink;
+pub use versions::{BuildMetadata, Prereleases, VersionScheme, VersionSelector};
+pub use warning::DownloadWarning;
 
 pub struct DownloadInfo<'a> {
     pub package: &'a Package,
     pub version: Version,
+    /// The URL of the artifact, the mirror's if one had to be used.
+    pub url: String,
     pub size: u64,
+    /// The bytes transferred, less than `size` if a compressed transfer has been decoded.
+    pub encoded_size: u64,
     pub compressed_path: PathBuf,
     pub uncompressed_path: PathBuf,
+    pub accepts_ranges: bool,
+    pub status: DownloadStatus,
+    /// Whether a gzip transfer encoding has been undone, see `DownloadOptions::decode_content`.
+    pub content_decoded: bool,
+    pub artifact_kind: ArtifactKind,
+    /// Where `download_and_extract` placed an artifact that isn't unpacked.
+    pub staged_path: Option<PathBuf>,
+    /// The verified provenance, if `DownloadOptions::provenance` asked for it.
+    pub provenance: Option<Provenance>,
+    /// The SBOM downloaded next to the artifact.
+    pub sbom_path: Option<PathBuf>,
+    /// The release notes saved next to the artifact, see `DownloadOptions::changelog`.
+    pub changelog_path: Option<PathBuf>,
+    /// The accepted license stored next to the artifact, see `DownloadOptions::license_url`.
+    pub license_path: Option<PathBuf>,
+    pub warnings: Vec<DownloadWarning>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    /// The artifact has been transferred.
+    Downloaded,
+    /// The local artifact is still up to date, nothing has been transferred.
+    NotModified,
+    /// The artifact has been unpacked while downloading and hasn't been stored.
+    Unpacked,
+}
+
+/// The URL a manifest resolved to and the version it downloads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedDownload {
+    pub url: String,
+    pub version: Version,
+    /// The version as it's put into URLs, e.g. `1.2.3%2Bbuild.7` or the listed `2024.05`.
+    pub raw_version: String,
+}
+
+#[cfg(feature = "blocking")]
+pub struct ProbeInfo {
+    pub url: String,
+    pub version: Version,
+    pub size: Option<u64>,
+    pub accepts_ranges: bool,
 }
 
 struct DownloadPath {
@@ -19,114 +197,1446 @@ struct DownloadPath {
 }
 
 impl DownloadPath {
-    fn from(download_url: &str) -> BoxedResult<Self> {
+    /// Fails with a `DownloadError::InvalidUrl` if `download_url` has no file name in its
+    /// path or no `#fragment` naming the compressed file.
+    fn from(download_url: &str) -> Result<Self, DownloadError> {
         use reqwest::Url;
 
-        let url = Url::parse(download_url)?;
+        let invalid =
+            |reason: &str| DownloadError::InvalidUrl(format!("{} {}", download_url, reason));
+        let url = Url::parse(download_url).map_err(|e| invalid(&format!("isn't a URL: {}", e)))?;
         let uncompressed_path = url
             .path_segments()
             .and_then(|segments| segments.last())
-            .expect("Could not extract uncompressed filename");
+            .ok_or_else(|| invalid("has no file name in its path"))?;
 
         let compressed_path = url
             .fragment()
-            .expect("Could not extract compressed filename");
+            .ok_or_else(|| invalid("has no #fragment naming the compressed file"))?;
 
         Ok(Self {
-            compressed_path: Path::new(compressed_path).to_owned(),
-            uncompressed_path: Path::new(uncompressed_path).to_owned(),
+            compressed_path: PathBuf::from(filename::percent_decoded(compressed_path)),
+            uncompressed_path: PathBuf::from(filename::percent_decoded(uncompressed_path)),
         })
     }
 }
 
+/// Counts the bytes read, e.g. what's left of a transfer after decoding it.
+#[cfg(feature = "blocking")]
+struct Counted<R> {
+    inner: R,
+    count: u64,
+}
+
+#[cfg(feature = "blocking")]
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "blocking")]
 struct DownloadProgress<'a, R> {
     inner: R,
+    started: Instant,
     progress_bar: &'a ProgressBar,
+    transferred: u64,
+    total: Option<u64>,
+    package: &'a str,
+    options: &'a DownloadOptions,
+    throughput: Option<Throughput>,
 }
 
+#[cfg(feature = "blocking")]
 impl<'a, R: Read> Read for DownloadProgress<'a, R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.options.deadline_passed() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "The deadline passed",
+            ));
+        }
+
         self.inner.read(buf).map(|n| {
             self.progress_bar.inc(n as u64);
+            self.transferred += n as u64;
+            self.options.wait_for_slot(self.package);
+            self.options.throttle(self.package, n as u64);
+
+            if let Some(limit) = self.options.max_bytes_per_second.filter(|limit| *limit > 0) {
+                use std::thread;
+                use std::time::Duration;
+
+                let expected = Duration::from_secs_f64(self.transferred as f64 / limit as f64);
+                let elapsed = self.started.elapsed();
+                if expected > elapsed {
+                    thread::sleep(expected - elapsed);
+                }
+            }
+
+            if let Some(throughput) = &mut self.throughput {
+                throughput.record(n as u64);
+                if throughput.should_report() {
+                    let speed = throughput.bytes_per_second();
+                    let eta = self
+                        .total
+                        .and_then(|total| throughput.eta(total.saturating_sub(self.transferred)));
+                    let appearance = &self.options.progress;
+                    let rate =
+                        speed::format_rate(speed, appearance.rate_unit, appearance.unit_prefix);
+                    self.progress_bar.set_message(&match eta {
+                        Some(eta) => format!("{}, {}", rate, progress::format_duration(eta)),
+                        None => rate,
+                    });
+                    let package = self.package;
+                    self.options
+                        .record(|metrics| metrics.speed(package, speed, eta));
+                }
+            }
+
+            let (package, transferred, total) = (self.package, self.transferred, self.total);
+            self.options.emit(|| DownloadEvent::Progress {
+                package: package.to_owned(),
+                transferred,
+                total,
+            });
             n
         })
     }
 }
 
-fn assemble_download_url(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "resolve", skip_all, fields(package = %manifest.package.name))
+)]
+pub(crate) fn assemble_download_url(
     manifest: &Manifest,
-    req: Option<VersionReq>,
-) -> Option<(String, Version)> {
+    selector: &VersionSelector,
+    options: &DownloadOptions,
+) -> Option<ResolvedDownload> {
     use just_versions::find_matching_version;
 
+    let (req, prereleases) = match selector {
+        VersionSelector::Matching(req) => (Some(req.clone()), options.prereleases),
+        VersionSelector::Stable => (None, Prereleases::Exclude),
+        VersionSelector::Latest => (None, Prereleases::Include),
+        VersionSelector::Lts => (Some(options.lts.clone()?), Prereleases::Exclude),
+    };
+
     let download_url = manifest.download.url.as_str();
-    manifest
-        .versions
-        .as_ref()
-        .and_then(|versions| {
-            find_matching_version(versions, req).and_then(|version| {
-                let url = download_url.replace("{version}", version.to_string().as_str());
-
-                Some((url, version))
-            })
+    let listed = match manifest.versions {
+        Some(_) => versions::listed_raw(manifest),
+        None => discovery::discover(manifest, options),
+    };
+    // Yanked versions are only downloaded if they're asked for exactly.
+    let allowed = |version: &Version| !options.is_yanked(version) || selector.pins(version);
+    let find_allowed = || {
+        let candidates: Vec<Version> = versions::parsed(&listed)
+            .into_iter()
+            .filter(|version| allowed(version))
+            .collect();
+
+        versions::find_matching_version(&candidates, req.as_ref(), prereleases)
+    };
+    let matching = match (prereleases, manifest.versions.as_ref()) {
+        (Prereleases::Exclude, Some(versions)) => find_matching_version(versions, req.clone())
+            .filter(|version| allowed(version))
+            .or_else(find_allowed),
+        _ => find_allowed(),
+    };
+
+    let build_metadata = options.build_metadata;
+    matching
+        .map(|version| (versions::url_version(&version, build_metadata), version))
+        .or_else(|| {
+            versions::find_unversioned(&listed, req.as_ref(), options.version_scheme.as_deref())
         })
         .or_else(|| {
-            manifest.download.version.as_ref().and_then(|version| {
-                let url = download_url.replace("{version}", version.to_string().as_str());
+            manifest
+                .download
+                .version
+                .as_ref()
+                .filter(|version| allowed(*version))
+                .map(|version| {
+                    (
+                        versions::url_version(version, build_metadata),
+                        version.clone(),
+                    )
+                })
+        })
+        .map(|(raw_version, version)| {
+            let template = options.url_template_for(&version).unwrap_or(download_url);
+
+            ResolvedDownload {
+                url: versions::substitute(template, &raw_version),
+                version,
+                raw_version,
+            }
+        })
+}
+
+/// The URL and version to download, rejecting versions the policy of `options` forbids.
+fn resolve(
+    manifest: &Manifest,
+    selector: &VersionSelector,
+    options: &DownloadOptions,
+) -> BoxedResult<ResolvedDownload> {
+    let package = manifest.package.name.as_str();
+    let resolved = assemble_download_url(manifest, selector, options).ok_or_else(|| {
+        let message = match selector {
+            VersionSelector::Lts if options.lts.is_none() => format!(
+                "No LTS requirement is set to resolve 'lts' of '{}'",
+                package
+            ),
+            _ => format!("No version of '{}' matches '{}'", package, selector),
+        };
+        DownloadError::NotFound(message)
+    })?;
+    versions::check_substituted(&resolved.url)?;
+    match &options.minimum_version {
+        Some(minimum) if resolved.version < *minimum => Err(DownloadError::Policy(format!(
+            "Version {} of '{}' is older than the minimum acceptable version {}",
+            resolved.version, package, minimum
+        ))
+        .into()),
+        _ => Ok(resolved),
+    }
+}
+
+/// Appends `suffix` to the file name, e.g. `tool.zip` becomes `tool.zip.part`.
+pub(crate) fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+
+    PathBuf::from(sibling)
+}
+
+/// A name for the in-flight download of `dest_path` no other download uses, e.g.
+/// `tool.zip.4711-1a2b3c.part`, made unique by the process id and the time.
+fn part_path(dest_path: &Path) -> PathBuf {
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::UNIX_EPOCH;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    let suffix = format!(
+        ".{}-{:x}{:x}.part",
+        process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+
+    sibling_path(dest_path, &suffix)
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    use reqwest::header::CONTENT_LENGTH;
+
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|hv| hv.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+/// Whether the body is gzip encoded for the transfer and `options` asks for decoding it.
+#[cfg(feature = "blocking")]
+fn decodes_content(headers: &HeaderMap, options: &DownloadOptions) -> bool {
+    use reqwest::header::CONTENT_ENCODING;
+
+    options.decode_content
+        && cfg!(feature = "archives")
+        && headers
+            .get(CONTENT_ENCODING)
+            .and_then(|hv| hv.to_str().ok())
+            .map(|value| value.trim().eq_ignore_ascii_case("gzip"))
+            .unwrap_or(false)
+}
+
+/// The body of a response, gunzipped if `decoded` is set.
+#[cfg(all(feature = "blocking", feature = "archives"))]
+fn decoded_body<'a, R: Read + 'a>(body: R, decoded: bool) -> Box<dyn Read + 'a> {
+    use flate2::read::GzDecoder;
+
+    if decoded {
+        Box::new(GzDecoder::new(body))
+    } else {
+        Box::new(body)
+    }
+}
+
+#[cfg(all(feature = "blocking", not(feature = "archives")))]
+fn decoded_body<'a, R: Read + 'a>(body: R, _decoded: bool) -> Box<dyn Read + 'a> {
+    Box::new(body)
+}
+
+fn accepts_ranges(headers: &HeaderMap) -> bool {
+    use reqwest::header::ACCEPT_RANGES;
+
+    headers
+        .get(ACCEPT_RANGES)
+        .and_then(|hv| hv.to_str().ok())
+        .map(|value| value.trim().eq_ignore_ascii_case("bytes"))
+        .unwrap_or(false)
+}
+
+fn last_modified(headers: &HeaderMap) -> Option<SystemTime> {
+    use reqwest::header::LAST_MODIFIED;
+
+    headers
+        .get(LAST_MODIFIED)
+        .and_then(|hv| hv.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+/// The URL and version `req` resolves to, without contacting the download server.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn resolve_url(
+    manifest: &Manifest,
+    req: impl Into<VersionSelector>,
+) -> BoxedResult<ResolvedDownload> {
+    resolve_url_with_options(manifest, req, &DownloadOptions::default())
+}
+
+/// Like `resolve_url`, but resolving the version as `options` ask for.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn resolve_url_with_options(
+    manifest: &Manifest,
+    req: impl Into<VersionSelector>,
+    options: &DownloadOptions,
+) -> BoxedResult<ResolvedDownload> {
+    let selector = req.into();
+
+    resolve(manifest, &selector, options)
+        .map_err(|e| error::with_context(e, &manifest.package.name, &selector, None))
+}
+
+/// Asks the server about the artifact without transferring it.
+#[cfg(feature = "blocking")]
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn probe(manifest: &Manifest, req: impl Into<VersionSelector>) -> BoxedResult<ProbeInfo> {
+    probe_with_options(manifest, req, &DownloadOptions::default())
+}
+
+/// Like `probe`, but with the proxy and host settings of `options`.
+#[cfg(feature = "blocking")]
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn probe_with_options(
+    manifest: &Manifest,
+    req: impl Into<VersionSelector>,
+    options: &DownloadOptions,
+) -> BoxedResult<ProbeInfo> {
+    use log::info;
+
+    let package = manifest.package.name.as_str();
+    let selector = req.into();
+    let ResolvedDownload { url, version, .. } = resolve(manifest, &selector, options)
+        .map_err(|e| error::with_context(e, package, &selector, None))?;
+    info!("Probing {}...", url);
 
-                Some((url, version.clone()))
-            })
+    let response = options
+        .client(&url)
+        .and_then(|client| {
+            Ok(options
+                .send(&client, client.head(&url))?
+                .error_for_status()?)
         })
+        .map_err(|e| error::with_context(e, package, &selector, Some((&url, &version))))?;
+
+    Ok(ProbeInfo {
+        size: content_length(response.headers()),
+        accepts_ranges: accepts_ranges(response.headers()),
+        url,
+        version,
+    })
+}
+
+#[cfg(feature = "blocking")]
+pub fn download(manifest: &Manifest, req: impl Into<VersionSelector>) -> BoxedResult<DownloadInfo> {
+    download_with_options(manifest, req, &DownloadOptions::default())
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_with_options<'a>(
+    manifest: &'a Manifest,
+    req: impl Into<VersionSelector>,
+    options: &DownloadOptions,
+) -> BoxedResult<DownloadInfo<'a>> {
+    transfer(manifest, &req.into(), options, None)
 }
 
-pub fn download(manifest: &Manifest, req: Option<VersionReq>) -> BoxedResult<DownloadInfo> {
-    use indicatif::ProgressStyle;
-    use log::{debug, info};
-    use reqwest::header::{HeaderValue, CONTENT_LENGTH};
-    use std::fs::OpenOptions;
+/// Stores the artifact, or unpacks it on the fly into `unpack_into` if it's a tarball.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "download", skip_all, fields(package = %manifest.package.name))
+)]
+#[cfg(feature = "blocking")]
+fn transfer<'a>(
+    manifest: &'a Manifest,
+    selector: &VersionSelector,
+    options: &DownloadOptions,
+    unpack_into: Option<&Path>,
+) -> BoxedResult<DownloadInfo<'a>> {
+    let package = manifest.package.name.as_str();
+    let result = try_transfer(manifest, selector, options, unpack_into)
+        .map_err(|e| error::with_context(e, package, selector, None));
+    finished(package, &result, options);
+
+    result
+}
+
+/// Tells the listeners and the `after_download` hook how the download of `package` ended.
+fn finished(package: &str, result: &BoxedResult<DownloadInfo>, options: &DownloadOptions) {
+    match result {
+        Ok(download_info) => options.emit(|| DownloadEvent::Finished {
+            package: package.to_owned(),
+            size: download_info.size,
+        }),
+        Err(e) => options.emit(|| DownloadEvent::Failed {
+            package: package.to_owned(),
+            error: e.to_string(),
+        }),
+    }
+    if let Some(after_download) = &options.after_download {
+        after_download.after_download(package, result);
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn try_transfer<'a>(
+    manifest: &'a Manifest,
+    selector: &VersionSelector,
+    options: &DownloadOptions,
+    unpack_into: Option<&Path>,
+) -> BoxedResult<DownloadInfo<'a>> {
+    let package = manifest.package.name.as_str();
+    options.check_deadline()?;
+    let started = Instant::now();
+    let resolved = resolve(manifest, selector, options)?;
+    let (download_url, version) = (resolved.url.as_str(), &resolved.version);
+    let mut download_path = DownloadPath::from(download_url)?;
+    download_path.compressed_path =
+        options.destination(&download_path.compressed_path, package, version);
+    options.record(|metrics| metrics.phase_finished(package, Phase::Resolve, started.elapsed()));
+    options.emit(|| DownloadEvent::Resolved {
+        package: package.to_owned(),
+        version: version.clone(),
+        url: download_url.to_owned(),
+    });
+    options.report(download_url, |telemetry, host| telemetry.started(host));
+
+    let result = transfer_version(
+        manifest,
+        selector,
+        &resolved,
+        download_path,
+        options,
+        unpack_into,
+    )
+    .map_err(|e| error::with_context(e, package, selector, Some((download_url, version))));
+    options.report(download_url, |telemetry, host| match &result {
+        Ok(download_info) => {
+            telemetry.succeeded(host, download_info.encoded_size, started.elapsed())
+        }
+        Err(e) => telemetry.failed(host, ErrorCategory::of(&**e), started.elapsed()),
+    });
+
+    result
+}
+
+/// Downloads the `resolved` version from its URL or one of the mirrors.
+#[cfg(feature = "blocking")]
+fn transfer_version<'a>(
+    manifest: &'a Manifest,
+    selector: &VersionSelector,
+    resolved: &ResolvedDownload,
+    mut download_path: DownloadPath,
+    options: &DownloadOptions,
+    unpack_into: Option<&Path>,
+) -> BoxedResult<DownloadInfo<'a>> {
+    use log::{debug, warn};
+
+    let package = manifest.package.name.as_str();
+    let (download_url, version) = (resolved.url.as_str(), &resolved.version);
+    let version_options;
+    let options = match options.checksum_for(version) {
+        Some(checksum) => {
+            version_options = DownloadOptions {
+                checksum: Some(checksum.clone()),
+                ..options.clone()
+            };
+            &version_options
+        }
+        None => options,
+    };
+
+    #[cfg(feature = "tuf")]
+    let tuf_options;
+    #[cfg(feature = "tuf")]
+    let options = match &options.tuf {
+        Some(tuf) => {
+            let target_name = download_path.uncompressed_path.to_string_lossy();
+            tuf_options = DownloadOptions {
+                checksum: Some(tuf::trusted_checksum(tuf, &target_name)?),
+                ..options.clone()
+            };
+            &tuf_options
+        }
+        None => options,
+    };
+
+    let mut urls = options.ordered_urls(download_url, &resolved.raw_version)?;
+
+    if options.offline {
+        return cached(manifest, version, &urls[0], &download_path);
+    }
+
+    // The server's name for the artifact keeps what the URL can't, like non-ASCII characters.
+    // It's asked for first, as everything below depends on where the artifact is stored.
+    if options.content_disposition {
+        if let Some(name) = suggested_name(&urls[0], options) {
+            debug!("Storing the artifact as {:?}, as the server suggests", name);
+            download_path.compressed_path = download_path.compressed_path.with_file_name(name);
+        }
+    }
+
+    // Another process downloading the same artifact is waited for, and its artifact reused.
+    let lock = lock::DownloadLock::acquire(&platform::long_path(&download_path.compressed_path))?;
+    if options.force {
+        remove_previous(&platform::long_path(&download_path.compressed_path))?;
+    }
+    if lock.waited() && platform::long_path(&download_path.compressed_path).exists() {
+        return cached(manifest, version, &urls[0], &download_path);
+    }
+
+    let intact = |artifact: &Path| is_intact(artifact, &urls[0], options);
+    let existing = existing(
+        manifest,
+        version,
+        &urls[0],
+        &mut download_path,
+        options,
+        intact,
+    )?;
+    if let Some(download_info) = existing {
+        return Ok(download_info);
+    }
+
+    if let Some(before_download) = &options.before_download {
+        before_download.before_download(&PendingDownload {
+            package,
+            version,
+            url: download_url,
+            destination: &download_path.compressed_path,
+        })?;
+    }
+
+    let license_path = license::accept(
+        package,
+        resolved,
+        &platform::long_path(&download_path.compressed_path),
+        options,
+    )?;
+
+    let mut warnings = Vec::new();
+    if options.is_yanked(version) {
+        warn!("Downloading yanked version {} of '{}'", version, package);
+        warnings.push(DownloadWarning::Yanked(version.clone()));
+    }
+
+    if options.mirror_stats && urls.len() > 1 {
+        mirrors::rank(&mut urls);
+    }
+
+    let mut attempt = 0;
+    let mut archive_tried = false;
+    loop {
+        let url = &urls[attempt % urls.len()];
+        if attempt > 0 {
+            options.record(|metrics| metrics.retried(package, attempt));
+            let previous = &urls[(attempt - 1) % urls.len()];
+            if previous != url {
+                options.emit(|| DownloadEvent::MirrorSwitched {
+                    package: package.to_owned(),
+                    from: previous.clone(),
+                    to: url.clone(),
+                });
+                warnings.push(DownloadWarning::MirrorFailover {
+                    from: previous.clone(),
+                    to: url.clone(),
+                });
+            }
+        }
+
+        let client = options.client(url)?;
+        let attempting = Instant::now();
+        let mut result = fetch(
+            &client,
+            manifest,
+            resolved,
+            url,
+            &download_path,
+            options,
+            unpack_into,
+        );
+        // Whatever failed once the deadline passed, it's because of the deadline.
+        if result.is_err() && options.deadline_passed() {
+            result = Err(DownloadError::TimedOut.into());
+        }
+        if let (true, Some(retry_budget)) = (attempt > 0, &options.retry_budget) {
+            retry_budget.spend(attempting.elapsed());
+        }
+        // Nothing is learned about the throughput from an artifact that's up to date.
+        let transferred = result
+            .as_ref()
+            .map(|download_info| download_info.encoded_size);
+        if options.mirror_stats && !matches!(transferred, Ok(0)) {
+            mirrors::record(
+                url,
+                transferred.ok().map(|bytes| (bytes, attempting.elapsed())),
+            );
+        }
+
+        let exhausted = matches!(&result, Err(e) if !should_retry(&**e, attempt, options));
+        if exhausted && options.archive_fallback && !archive_tried {
+            archive_tried = true;
+            if let Some(snapshot) = archived(download_url, options) {
+                warn!(
+                    "Downloading the Internet Archive's snapshot {}...",
+                    snapshot
+                );
+                result = fetch(
+                    &options.client(&snapshot)?,
+                    manifest,
+                    resolved,
+                    &snapshot,
+                    &download_path,
+                    options,
+                    unpack_into,
+                );
+                if result.is_ok() {
+                    warnings.push(DownloadWarning::Archived { url: snapshot });
+                }
+            }
+        }
+
+        let retry = match &result {
+            Err(e) if should_retry(&**e, attempt, options) => Some(options.take_retry()),
+            _ => None,
+        };
+        match result {
+            Err(ref e) if retry == Some(Ok(())) => {
+                warn!("{}, downloading again...", e);
+                attempt += 1;
+            }
+            Ok(mut download_info) => {
+                warnings.append(&mut download_info.warnings);
+                download_info.warnings = warnings;
+                download_info.license_path = license_path;
+                if let Some(sbom_url) = &options.sbom_url {
+                    let artifact = platform::long_path(&download_info.compressed_path);
+                    let sbom_url = companion::url(sbom_url, &resolved.raw_version);
+                    download_info.sbom_path = Some(companion::fetch(
+                        &options.client(&sbom_url)?,
+                        &artifact,
+                        &sbom_url,
+                        options,
+                    )?);
+                }
+                if options.changelog {
+                    let artifact = platform::long_path(&download_info.compressed_path);
+                    download_info.changelog_path = changelog::fetch(
+                        manifest, resolved, &artifact, options,
+                    )
+                    .unwrap_or_else(|e| {
+                        warn!("Could not fetch the release notes of '{}': {}", package, e);
+                        None
+                    });
+                }
+
+                return Ok(download_info);
+            }
+            Err(e) => {
+                return Err(DownloadError::Failed(Box::new(Failure {
+                    package: package.to_owned(),
+                    requirement: selector.to_string(),
+                    version: Some(version.clone()),
+                    url: Some(url.clone()),
+                    retries: attempt,
+                    source: match retry {
+                        Some(Err(reason)) => {
+                            DownloadError::Policy(format!("{}, {}", e, reason)).into()
+                        }
+                        _ => e,
+                    },
+                }))
+                .into())
+            }
+        }
+    }
+}
+
+/// The file name the server suggests for the artifact at `url` with `Content-Disposition`.
+#[cfg(feature = "blocking")]
+fn suggested_name(url: &str, options: &DownloadOptions) -> Option<String> {
+    use log::debug;
+
+    let response = options.client(url).and_then(|client| {
+        Ok(options
+            .send(&client, client.head(url))?
+            .error_for_status()?)
+    });
+    match response {
+        Ok(response) => filename::from_disposition(response.headers()),
+        Err(e) => {
+            debug!("Could not ask {} for the name of the artifact: {}", url, e);
+            None
+        }
+    }
+}
+
+/// The Internet Archive's snapshot of `url`, if the artifact can be verified without its host.
+#[cfg(feature = "blocking")]
+fn archived(url: &str, options: &DownloadOptions) -> Option<String> {
+    use log::warn;
+
+    if !options.verifies() {
+        warn!(
+            "Not looking for a snapshot of {}, it couldn't be verified",
+            url
+        );
+        return None;
+    }
+
+    wayback::snapshot(url, options).unwrap_or_else(|e| {
+        warn!("Could not look for a snapshot of {}: {}", url, e);
+        None
+    })
+}
+
+/// Whether the existing `artifact` matches the checksum, or the size announced for `url`
+/// without one. It's assumed to be if the server can't be asked.
+#[cfg(feature = "blocking")]
+fn is_intact(artifact: &Path, url: &str, options: &DownloadOptions) -> bool {
+    use log::{debug, warn};
+    use std::fs;
+
+    if let Some(checksum) = &options.checksum {
+        return match checksum.verify(artifact, options.allow_insecure_digests) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Downloading {:?} again: {}", artifact, e);
+                false
+            }
+        };
+    }
+
+    let announced = options
+        .client(url)
+        .and_then(|client| {
+            Ok(options
+                .send(&client, client.head(url))?
+                .error_for_status()?)
+        })
+        .map(|response| content_length(response.headers()));
+    match (announced, fs::metadata(artifact)) {
+        (Ok(Some(size)), Ok(metadata)) if size != metadata.len() => {
+            warn!(
+                "Downloading {:?} again, it has {} bytes instead of {}",
+                artifact,
+                metadata.len(),
+                size
+            );
+            false
+        }
+        (Err(e), _) => {
+            debug!(
+                "Reusing {:?}, its size couldn't be checked: {}",
+                artifact, e
+            );
+            true
+        }
+        _ => true,
+    }
+}
+
+/// The artifact already in the destination, if `options.collision` keeps it. `Rename` moves
+/// `download_path` to a free name instead, and `Reuse` keeps the artifact if it's `intact`.
+fn existing<'a>(
+    manifest: &'a Manifest,
+    version: &Version,
+    url: &str,
+    download_path: &mut DownloadPath,
+    options: &DownloadOptions,
+    intact: impl FnOnce(&Path) -> bool,
+) -> BoxedResult<Option<DownloadInfo<'a>>> {
+    if options.if_newer || !platform::long_path(&download_path.compressed_path).exists() {
+        return Ok(None);
+    }
+
+    match options.collision {
+        CollisionPolicy::Overwrite => Ok(None),
+        CollisionPolicy::Skip => cached(manifest, version, url, download_path).map(Some),
+        CollisionPolicy::Rename => {
+            download_path.compressed_path = unique_path(&download_path.compressed_path);
+            Ok(None)
+        }
+        CollisionPolicy::Error => {
+            Err(DownloadError::AlreadyExists(download_path.compressed_path.clone()).into())
+        }
+        CollisionPolicy::Reuse => {
+            if intact(&platform::long_path(&download_path.compressed_path)) {
+                cached(manifest, version, url, download_path).map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Removes `dest_path`, its stored ETag and the `.part` files left by interrupted downloads
+/// of it, for `DownloadOptions::force`. Must be called while holding the download lock.
+#[cfg(feature = "blocking")]
+fn remove_previous(dest_path: &Path) -> io::Result<()> {
+    use log::debug;
+    use std::fs;
+
+    for path in &[dest_path.to_owned(), sibling_path(dest_path, ".etag")] {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+
+    let (parent, file_name) = match (dest_path.parent(), dest_path.file_name()) {
+        (Some(parent), Some(file_name)) if parent.exists() => (parent, file_name),
+        _ => return Ok(()),
+    };
+    let prefix = format!("{}.", file_name.to_string_lossy());
+    for entry in fs::read_dir(parent)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if name.starts_with(&prefix) && name.ends_with(".part") {
+            debug!("Removing the stale {:?}", path);
+            fs::remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `path` with a number appended to the file stem, e.g. `tool-1.tar.gz`, which doesn't exist
+/// yet.
+fn unique_path(path: &Path) -> PathBuf {
+    (1..)
+        .map(|n| options::suffixed(path, &n.to_string()))
+        .find(|candidate| !platform::long_path(candidate).exists())
+        .expect("Ran out of file names")
+}
+
+/// The artifact as already present in the destination, for offline downloads,
+/// `CollisionPolicy::Skip` and `CollisionPolicy::Reuse`.
+fn cached<'a>(
+    manifest: &'a Manifest,
+    version: &Version,
+    url: &str,
+    download_path: &DownloadPath,
+) -> BoxedResult<DownloadInfo<'a>> {
+    use std::fs;
+
+    let dest_path = platform::long_path(&download_path.compressed_path);
+    if !dest_path.exists() {
+        return Err(format!(
+            "Offline, but {:?} has not been downloaded yet",
+            download_path.compressed_path
+        )
+        .into());
+    }
+
+    Ok(DownloadInfo {
+        package: &manifest.package,
+        version: version.clone(),
+        url: url.to_owned(),
+        artifact_kind: ArtifactKind::detect(&dest_path)?,
+        staged_path: None,
+        provenance: None,
+        sbom_path: None,
+        changelog_path: None,
+        license_path: None,
+        warnings: Vec::new(),
+        size: fs::metadata(&dest_path)?.len(),
+        accepts_ranges: false,
+        compressed_path: download_path.compressed_path.clone(),
+        uncompressed_path: download_path.uncompressed_path.clone(),
+        status: DownloadStatus::NotModified,
+        content_decoded: false,
+        encoded_size: 0,
+    })
+}
+
+/// An artifact changed while downloading its ranges is downloaded again from scratch.
+#[cfg(feature = "blocking")]
+fn has_changed(e: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<DownloadError>(),
+        Some(DownloadError::Changed(_))
+    )
+}
+
+#[cfg(feature = "blocking")]
+fn is_verification_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<DownloadError>(),
+        Some(DownloadError::Verification(_))
+    )
+}
+
+/// Verification failures and network errors are worth another attempt. Other I/O errors,
+/// like an archive escaping its target or unpacking to too much, happen again.
+#[cfg(feature = "blocking")]
+fn should_retry(
+    e: &(dyn std::error::Error + 'static),
+    attempt: usize,
+    options: &DownloadOptions,
+) -> bool {
+    if is_verification_error(e) {
+        attempt < options.verification_retries
+    } else {
+        let is_network_error =
+            e.is::<io::Error>() && ErrorCategory::of(e) == ErrorCategory::Network;
+
+        attempt < options.retries
+            && (e.is::<reqwest::Error>() || is_network_error || has_changed(e))
+    }
+}
+
+#[cfg(feature = "blocking")]
+struct Verified {
+    artifact_kind: ArtifactKind,
+    provenance: Option<Provenance>,
+    warnings: Vec<DownloadWarning>,
+}
+
+/// Runs every verification `options` ask for on the downloaded, not yet finalized artifact.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(artifact = ?artifact)))]
+#[cfg(feature = "blocking")]
+fn verify(
+    client: &Client,
+    artifact: &Path,
+    download_url: &str,
+    raw_version: &str,
+    options: &DownloadOptions,
+) -> BoxedResult<Verified> {
+    let mut warnings = Vec::new();
+    verify_checksum(artifact, options, &mut warnings)?;
+
+    if let Some(policy) = &options.signatures {
+        if !signature::verify(client, policy, download_url, artifact, options)? {
+            warnings.push(DownloadWarning::MissingSignature);
+        }
+    }
+
+    let artifact_kind = verify_kind(artifact, options)?;
+
+    let provenance = match &options.provenance {
+        Some(policy) => Some(provenance::verify(policy, raw_version, artifact, options)?),
+        None => None,
+    };
+
+    if let Some(scanner) = &options.scanner {
+        match scanner.scan(artifact)? {
+            Verdict::Clean => {}
+            Verdict::Suspicious(reason) => warnings.push(DownloadWarning::Suspicious { reason }),
+            Verdict::Rejected(reason) => {
+                return Err(DownloadError::Policy(format!(
+                    "{:?} has been rejected by the scanner: {}",
+                    artifact, reason
+                ))
+                .into())
+            }
+        }
+    }
+
+    Ok(Verified {
+        artifact_kind,
+        provenance,
+        warnings,
+    })
+}
+
+/// Checks `artifact` against the checksum of `options`, warning if there's none or it's weak.
+fn verify_checksum(
+    artifact: &Path,
+    options: &DownloadOptions,
+    warnings: &mut Vec<DownloadWarning>,
+) -> BoxedResult<()> {
+    match &options.checksum {
+        Some(checksum) => {
+            checksum.verify(artifact, options.allow_insecure_digests)?;
+            if checksum.algorithm.is_insecure() {
+                warnings.push(DownloadWarning::WeakDigest(checksum.algorithm));
+            }
+        }
+        None if options.require_checksum => {
+            return Err(
+                DownloadError::Policy(format!("No checksum given for {:?}", artifact)).into(),
+            )
+        }
+        None => warnings.push(DownloadWarning::MissingChecksum),
+    }
+
+    Ok(())
+}
+
+/// The kind of `artifact`, checked against the one `options` expect.
+fn verify_kind(artifact: &Path, options: &DownloadOptions) -> BoxedResult<ArtifactKind> {
+    match options.artifact_kind {
+        Some(kind) => {
+            kind.verify(artifact)?;
+            Ok(kind)
+        }
+        None => Ok(ArtifactKind::detect(artifact)?),
+    }
+}
+
+/// Moves the verified artifact at `part_path` to `dest_path`, with the permissions, quarantine
+/// and modification time `options` ask for.
+fn finalize(
+    part_path: &Path,
+    dest_path: &Path,
+    download_url: &str,
+    artifact_kind: ArtifactKind,
+    last_modified: Option<SystemTime>,
+    options: &DownloadOptions,
+    warnings: &mut Vec<DownloadWarning>,
+) -> BoxedResult<()> {
+    use std::fs;
+
+    if options.make_executable || artifact_kind == ArtifactKind::AppImage {
+        platform::make_executable(part_path)?;
+    }
+    platform::apply_quarantine(part_path, download_url, options.quarantine)?;
+
+    if let Some(modified) = last_modified.filter(|_| !options.ignore_last_modified) {
+        use filetime::FileTime;
+
+        let mtime = FileTime::from_system_time(modified);
+        filetime::set_file_times(part_path, mtime, mtime)?;
+    }
+
+    if options.durable {
+        fs::File::open(part_path)?.sync_all()?;
+    }
+    if platform::replace(part_path, dest_path)? {
+        warnings.push(DownloadWarning::PendingReboot {
+            path: dest_path.to_owned(),
+        });
+    }
+    if options.durable {
+        platform::sync_parent(dest_path)?;
+    }
+
+    Ok(())
+}
+
+/// The artifact at `url` doesn't exist (anymore), if the server answered with `status`.
+#[cfg(feature = "blocking")]
+fn gone(status: reqwest::StatusCode, url: &str) -> Option<DownloadError> {
+    use reqwest::StatusCode;
+
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => Some(DownloadError::NotFound(format!(
+            "{} answered with {}",
+            url, status
+        ))),
+        _ => None,
+    }
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(url = download_url)))]
+#[cfg(feature = "blocking")]
+fn fetch<'a>(
+    client: &Client,
+    manifest: &'a Manifest,
+    resolved: &ResolvedDownload,
+    download_url: &str,
+    download_path: &DownloadPath,
+    options: &DownloadOptions,
+    unpack_into: Option<&Path>,
+) -> BoxedResult<DownloadInfo<'a>> {
+    use extract::ArchiveKind;
+    use log::{debug, info, log};
+    use reqwest::header::{HeaderValue, CONTENT_TYPE};
+    use reqwest::StatusCode;
+    use std::fs::{self, OpenOptions};
     use std::io::copy;
 
-    let (download_url, version) =
-        assemble_download_url(manifest, req).expect("No Download-URL or valid Version given");
+    options.check_deadline()?;
     info!("Downloading from {}...", download_url);
 
-    let response = reqwest::get(&download_url)?;
-    let byte_size: u64 = response
+    let version = &resolved.version;
+    let dest_path = platform::long_path(&download_path.compressed_path);
+
+    let package = manifest.package.name.as_str();
+    let connecting = Instant::now();
+    #[cfg(feature = "tracing")]
+    let connect_span = tracing::info_span!("connect").entered();
+
+    let mut request = client.get(download_url);
+    if options.if_newer {
+        request = conditional::with_validators(request, &dest_path);
+    }
+    if options.decode_content && cfg!(feature = "archives") {
+        use reqwest::header::ACCEPT_ENCODING;
+
+        request = request.header(ACCEPT_ENCODING, "gzip");
+    }
+
+    let response = interstitial::follow(options.send(client, request)?, options)?;
+    if let Some(e) = gone(response.status(), download_url) {
+        return Err(e.into());
+    }
+    // Other error statuses are retried like the network errors they're returned as.
+    let response = response.error_for_status()?;
+    options.record(|metrics| metrics.phase_finished(package, Phase::Connect, connecting.elapsed()));
+
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info!(status = response.status().as_u16(), "connected");
+        drop(connect_span);
+    }
+    if response.status() == StatusCode::NOT_MODIFIED {
+        options.record(|metrics| metrics.cache_hit(package));
+        info!(
+            "'{}' is already up to date.",
+            manifest.package.name.as_str()
+        );
+
+        return Ok(DownloadInfo {
+            package: &manifest.package,
+            version: version.clone(),
+            url: download_url.to_owned(),
+            artifact_kind: ArtifactKind::detect(&dest_path)?,
+            staged_path: None,
+            provenance: None,
+            sbom_path: None,
+            changelog_path: None,
+            license_path: None,
+            warnings: Vec::new(),
+            size: fs::metadata(&dest_path)?.len(),
+            accepts_ranges: accepts_ranges(response.headers()),
+            compressed_path: download_path.compressed_path.clone(),
+            uncompressed_path: download_path.uncompressed_path.clone(),
+            status: DownloadStatus::NotModified,
+            content_decoded: false,
+            encoded_size: 0,
+        });
+    }
+
+    let content_type = response
         .headers()
-        .get(CONTENT_LENGTH)
-        .and_then(|hv: &HeaderValue| hv.to_str().ok())
-        .and_then(|value| value.parse::<u64>().ok())
-        .expect("No (numeric) Content-Length given");
+        .get(CONTENT_TYPE)
+        .and_then(|hv: &HeaderValue| hv.to_str().ok());
+    if !options.accepts_content_type(content_type) {
+        return Err(DownloadError::Verification(format!(
+            "Unexpected Content-Type {:?} from {}, expected one of {:?}",
+            content_type,
+            response.url(),
+            options.expected_content_types
+        ))
+        .into());
+    }
+
+    let insecure_transport = response.url().scheme() == "http";
+    // Chunked responses, like most compressed transfers, don't tell their length.
+    let byte_size = content_length(response.headers());
+    let accepts_ranges = accepts_ranges(response.headers());
+    let content_decoded = decodes_content(response.headers(), options);
+    let range_validator = conditional::range_validator(response.headers());
+    let last_modified = last_modified(response.headers());
+    let etag = conditional::etag(response.headers());
 
-    debug!("Downloaded {} Bytes", byte_size);
+    match byte_size {
+        Some(byte_size) => debug!("Downloading {} Bytes", byte_size),
+        None => debug!("Downloading an unknown number of Bytes"),
+    }
+    options.emit(|| DownloadEvent::Started {
+        package: package.to_owned(),
+        url: download_url.to_owned(),
+        size: byte_size,
+    });
 
-    let pb = ProgressBar::new(byte_size);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-        .progress_chars("=>"));
+    let pb = progress::bar(byte_size, options);
 
+    #[cfg(feature = "tracing")]
+    let transfer_span = tracing::info_span!("transfer", bytes = ?byte_size).entered();
+
+    let transferring = Instant::now();
+    let artifact_url = response.url().to_string();
     let mut source = DownloadProgress {
         progress_bar: &pb,
         inner: response,
+        started: Instant::now(),
+        transferred: 0,
+        total: byte_size,
+        package,
+        options,
+        throughput: options.progress.speed_window.map(Throughput::new),
     };
-    let download_path = DownloadPath::from(&download_url)?;
-
-    info!("Downloading into {:?}", download_path.compressed_path);
-    let mut dest = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&download_path.compressed_path)
-        .unwrap_or_else(|e| {
-            panic!(
-                "Could not open compressed path {:?}: {:?}",
-                download_path.compressed_path, e
-            )
+
+    // The artifact can only be verified or scanned before unpacking if it's stored, and
+    // the limits of unpacking can only be applied to an artifact of known size.
+    let streamed = unpack_into
+        .filter(|_| {
+            options.checksum.is_none()
+                && !options.require_checksum
+                && options.provenance.is_none()
+                && options.signatures.is_none()
+                && options.scanner.is_none()
+        })
+        .and_then(|target| {
+            ArchiveKind::from_extension(&download_path.compressed_path)
+                .filter(|kind| kind.is_tar() && cfg!(feature = "archives"))
+                .and_then(|kind| Some((kind, target, byte_size?)))
         });
-    info!("Copy into {:?}", download_path.compressed_path);
+    if let Some((kind, target, byte_size)) = streamed {
+        log!(options.detail_level(), "Unpacking into {:?}", target);
+        let mut unpacked = Counted {
+            inner: decoded_body(&mut source, content_decoded),
+            count: 0,
+        };
+        extract::unpack_tar(
+            &mut unpacked,
+            kind,
+            target,
+            byte_size,
+            &options.extract_limits,
+        )?;
+        let size = unpacked.count;
+        drop(unpacked);
+        pb.finish();
+        options.record(|metrics| {
+            metrics.bytes_transferred(package, source.transferred);
+            metrics.phase_finished(package, Phase::Transfer, transferring.elapsed());
+        });
+
+        return Ok(DownloadInfo {
+            package: &manifest.package,
+            version: version.clone(),
+            url: download_url.to_owned(),
+            size,
+            encoded_size: source.transferred,
+            compressed_path: download_path.compressed_path.clone(),
+            uncompressed_path: download_path.uncompressed_path.clone(),
+            accepts_ranges,
+            status: DownloadStatus::Unpacked,
+            content_decoded,
+            artifact_kind: ArtifactKind::Archive(kind),
+            staged_path: None,
+            provenance: None,
+            sbom_path: None,
+            changelog_path: None,
+            license_path: None,
+            warnings: vec![DownloadWarning::MissingChecksum],
+        });
+    }
+
+    let part_path = part_path(&dest_path);
+    if let Some(parent) = part_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    log!(options.detail_level(), "Downloading into {:?}", part_path);
+    // Ranges of an encoded transfer couldn't be decoded on their own.
+    let ranged_size = byte_size.filter(|size| accepts_ranges && *size > 0 && !content_decoded);
+    let (download_size, encoded_size) = match ranged_size {
+        // Chunks of mirrors serving another build would go unnoticed without verification.
+        Some(byte_size) if options.swarm && options.verifies() && !options.mirrors.is_empty() => {
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            drop(source);
+            let mirror_urls = options.mirror_urls(&resolved.raw_version)?;
+            let mut mirrors = Vec::new();
+            for url in mirror_urls.iter().filter(|url| *url != download_url) {
+                let client = options.client(url)?;
+                let announced = options
+                    .send(&client, client.head(url.as_str()))
+                    .and_then(|response| Ok(response.error_for_status()?));
+                let headers = match announced {
+                    Ok(response) => response.headers().clone(),
+                    Err(e) => {
+                        debug!("Not downloading from the mirror {}: {}", url, e);
+                        continue;
+                    }
+                };
+                let validator = conditional::range_validator(&headers);
+                if content_length(&headers) != Some(byte_size)
+                    || !accepts_ranges(&headers)
+                    || validator.is_none()
+                {
+                    debug!(
+                        "Not downloading from the mirror {}, it announces another artifact \
+                         or no ranges of it",
+                        url
+                    );
+                    continue;
+                }
+                mirrors.push((client, url, validator));
+            }
+
+            let mut remotes = vec![(
+                client.clone(),
+                segmented::Remote {
+                    url: &artifact_url,
+                    validator: range_validator.as_deref(),
+                    options,
+                },
+            )];
+            for (client, url, validator) in &mirrors {
+                let remote = segmented::Remote {
+                    url,
+                    validator: validator.as_deref(),
+                    options,
+                };
+                remotes.push((client.clone(), remote));
+            }
+            log!(
+                options.detail_level(),
+                "Downloading {} from {} servers",
+                artifact_url,
+                remotes.len()
+            );
+            let transferred = AtomicU64::new(0);
+            let size = segmented::swarm(&remotes, &part_path, byte_size, &|n| {
+                pb.inc(n);
+                options.wait_for_slot(package);
+                options.throttle(package, n);
+                let transferred = transferred.fetch_add(n, Ordering::SeqCst) + n;
+                options.emit(|| DownloadEvent::Progress {
+                    package: package.to_owned(),
+                    transferred,
+                    total: Some(byte_size),
+                });
+            })?;
+
+            (size, size)
+        }
+        Some(byte_size) if options.segments > 1 => {
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            // The ranges are requested anew, the response of the whole artifact isn't needed.
+            drop(source);
+            log!(
+                options.detail_level(),
+                "Downloading {} segments of {}",
+                options.segments,
+                artifact_url
+            );
+            let transferred = AtomicU64::new(0);
+            let remote = segmented::Remote {
+                url: &artifact_url,
+                validator: range_validator.as_deref(),
+                options,
+            };
+            let size = segmented::download(
+                client,
+                &remote,
+                &part_path,
+                byte_size,
+                options.segments,
+                options.use_mmap(),
+                &|n| {
+                    pb.inc(n);
+                    options.wait_for_slot(package);
+                    options.throttle(package, n);
+                    let transferred = transferred.fetch_add(n, Ordering::SeqCst) + n;
+                    options.emit(|| DownloadEvent::Progress {
+                        package: package.to_owned(),
+                        transferred,
+                        total: Some(byte_size),
+                    });
+                },
+            )?;
 
-    let download_size = copy(&mut source, &mut dest)?;
+            (size, size)
+        }
+        _ => {
+            let mut dest = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&part_path)?;
+            log!(options.detail_level(), "Copy into {:?}", part_path);
+
+            let mut body = decoded_body(&mut source, content_decoded);
+            let size = copy(&mut body, &mut dest)?;
+            drop(body);
+
+            (size, source.transferred)
+        }
+    };
+    options.record(|metrics| {
+        metrics.bytes_transferred(package, encoded_size);
+        metrics.phase_finished(package, Phase::Transfer, transferring.elapsed());
+    });
+
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info!(bytes = download_size, "transferred");
+        drop(transfer_span);
+    }
 
     pb.finish();
+
+    let verifying = Instant::now();
+    let verified = verify(
+        client,
+        &part_path,
+        download_url,
+        &resolved.raw_version,
+        options,
+    );
+    options.record(|metrics| metrics.phase_finished(package, Phase::Verify, verifying.elapsed()));
+    let mut verified = match verified {
+        Ok(verified) => {
+            options.emit(|| DownloadEvent::Verified {
+                package: package.to_owned(),
+            });
+            verified
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            return Err(e);
+        }
+    };
+    let artifact_kind = verified.artifact_kind;
+    if insecure_transport {
+        verified.warnings.push(DownloadWarning::InsecureTransport {
+            url: download_url.to_owned(),
+        });
+    }
+
+    finalize(
+        &part_path,
+        &dest_path,
+        download_url,
+        artifact_kind,
+        last_modified,
+        options,
+        &mut verified.warnings,
+    )?;
+    if options.if_newer {
+        conditional::store_etag(etag.as_deref(), &dest_path)?;
+    }
     info!(
         "Download of '{}' has been completed.",
         manifest.package.name.as_str()
@@ -134,9 +1644,150 @@ pub fn download(manifest: &Manifest, req: Option<VersionReq>) -> BoxedResult<Dow
 
     Ok(DownloadInfo {
         package: &manifest.package,
-        version,
+        version: version.clone(),
+        url: download_url.to_owned(),
         size: download_size,
-        compressed_path: download_path.compressed_path.to_owned(),
-        uncompressed_path: download_path.uncompressed_path.to_owned(),
+        encoded_size,
+        compressed_path: download_path.compressed_path.clone(),
+        uncompressed_path: download_path.uncompressed_path.clone(),
+        accepts_ranges,
+        status: DownloadStatus::Downloaded,
+        content_decoded,
+        artifact_kind,
+        staged_path: None,
+        provenance: verified.provenance,
+        sbom_path: None,
+        changelog_path: None,
+        license_path: None,
+        warnings: verified.warnings,
     })
 }
+
+/// Downloads the artifact and unpacks it into `target`.
+#[cfg(feature = "blocking")]
+pub fn download_and_extract<'a>(
+    manifest: &'a Manifest,
+    req: impl Into<VersionSelector>,
+    options: &DownloadOptions,
+    target: &Path,
+) -> BoxedResult<DownloadInfo<'a>> {
+    let selector = req.into();
+    let mut download_info = transfer(manifest, &selector, options, Some(target))?;
+    if download_info.status == DownloadStatus::Unpacked {
+        return Ok(download_info);
+    }
+
+    download_info.staged_path = stage(&download_info, options, target).map_err(|e| {
+        error::with_context(
+            e,
+            &manifest.package.name,
+            &selector,
+            Some((&download_info.url, &download_info.version)),
+        )
+    })?;
+
+    Ok(download_info)
+}
+
+/// Unpacks or places the downloaded artifact in `target`, returning where a single file went.
+#[cfg(feature = "blocking")]
+fn stage(
+    download_info: &DownloadInfo,
+    options: &DownloadOptions,
+    target: &Path,
+) -> BoxedResult<Option<PathBuf>> {
+    let artifact = &download_info.compressed_path;
+    let staged_path = match download_info.artifact_kind {
+        ArtifactKind::Binary => {
+            let path = extract::place_with(
+                artifact,
+                target,
+                options.binary_name.as_deref(),
+                options.placement,
+            )?;
+            platform::make_executable(&path)?;
+            Some(path)
+        }
+        ArtifactKind::AppImage => {
+            let path = extract::place_with(
+                artifact,
+                target,
+                options.binary_name.as_deref(),
+                options.placement,
+            )?;
+            platform::make_executable(&path)?;
+            if options.extract_appimage {
+                platform::extract_appimage(&path, target)?;
+            }
+            Some(path)
+        }
+        ArtifactKind::Installer(_) => Some(extract::place_with(
+            artifact,
+            target,
+            None,
+            options.placement,
+        )?),
+        _ => {
+            extract::extract(artifact, target, &options.extract_limits)?;
+            None
+        }
+    };
+
+    Ok(staged_path)
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn reports_missing_artifacts_as_not_found() {
+        for status in &[StatusCode::NOT_FOUND, StatusCode::GONE] {
+            let e = gone(*status, "https://example.com/tool.zip").unwrap();
+            assert_eq!(e.category(), ErrorCategory::NotFound);
+        }
+    }
+
+    #[test]
+    fn leaves_other_statuses_to_the_retries() {
+        for status in &[
+            StatusCode::OK,
+            StatusCode::NOT_MODIFIED,
+            StatusCode::FORBIDDEN,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(gone(*status, "https://example.com/tool.zip").is_none());
+        }
+    }
+
+    #[test]
+    fn numbers_paths_until_one_is_free() {
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("just-download-unique-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("tool-1.tar.gz"), b"").unwrap();
+        fs::write(dir.join("tool-2.tar.gz"), b"").unwrap();
+
+        let unique = unique_path(&dir.join("tool.tar.gz"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(unique, dir.join("tool-3.tar.gz"));
+    }
+
+    #[test]
+    fn rejects_download_urls_without_a_compressed_file_name() {
+        for url in &["https://example.com/tool.zip", "not a url#tool.zip"] {
+            match DownloadPath::from(url) {
+                Err(DownloadError::InvalidUrl(_)) => {}
+                _ => panic!("{} was accepted", url),
+            }
+        }
+
+        let download_path =
+            DownloadPath::from("https://example.com/tool.exe#tool%20v1.zip").unwrap();
+        assert_eq!(download_path.compressed_path, PathBuf::from("tool v1.zip"));
+        assert_eq!(download_path.uncompressed_path, PathBuf::from("tool.exe"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Licenses which have to be accepted before an artifact is downloaded, as some commercial
+//! SDKs require.
+
+#[cfg(feature = "blocking")]
+use crate::{companion, metadata, options::DownloadOptions, DownloadError, ResolvedDownload};
+#[cfg(feature = "blocking")]
+use just_core::result::BoxedResult;
+use semver::Version;
+use std::fmt;
+#[cfg(feature = "blocking")]
+use std::path::{Path, PathBuf};
+
+/// Decides whether the license of a package is accepted, e.g. by asking the user.
+pub trait LicenseAcceptance: Send + Sync {
+    fn accept(&self, package: &str, version: &Version, license: &str) -> bool;
+}
+
+impl<F: Fn(&str, &Version, &str) -> bool + Send + Sync> LicenseAcceptance for F {
+    fn accept(&self, package: &str, version: &Version, license: &str) -> bool {
+        self(package, version, license)
+    }
+}
+
+impl fmt::Debug for dyn LicenseAcceptance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("LicenseAcceptance")
+    }
+}
+
+/// Downloads the license at `options.license_url` next to `artifact` once it's accepted.
+/// Fails if there's no `options.license_acceptance` or it doesn't accept the license.
+#[cfg(feature = "blocking")]
+pub(crate) fn accept(
+    package: &str,
+    resolved: &ResolvedDownload,
+    artifact: &Path,
+    options: &DownloadOptions,
+) -> BoxedResult<Option<PathBuf>> {
+    use log::info;
+    use std::fs;
+
+    let version = &resolved.version;
+    let url = match &options.license_url {
+        Some(license_url) => companion::url(license_url, &resolved.raw_version),
+        None => return Ok(None),
+    };
+    let license = metadata::get(&options.client(&url)?, &url, options)?
+        .ok_or_else(|| format!("No license of '{}' published at {}", package, url))?;
+
+    let accepted = options
+        .license_acceptance
+        .as_ref()
+        .map(|acceptance| acceptance.accept(package, version, &String::from_utf8_lossy(&license)))
+        .unwrap_or(false);
+    if !accepted {
+        return Err(DownloadError::Policy(format!(
+            "The license of '{}' at {} hasn't been accepted",
+            package, url
+        ))
+        .into());
+    }
+
+    let path = companion::path(artifact, &url)?;
+    info!(
+        "Storing the accepted license of '{}' in {:?}",
+        package, path
+    );
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, license)?;
+
+    Ok(Some(path))
+}
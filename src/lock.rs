@@ -0,0 +1,81 @@
+#[cfg(feature = "locking")]
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An advisory lock on the destination of an artifact, held while it's downloaded so
+/// several `just` processes don't download the same artifact at once.
+/// It's released when dropped or when the process exits. Without the `locking` feature
+/// nothing is locked, and concurrent processes may download the same artifact twice.
+pub(crate) struct DownloadLock {
+    #[cfg_attr(not(feature = "locking"), allow(dead_code))]
+    file: File,
+    waited: bool,
+}
+
+impl DownloadLock {
+    /// Locks `dest_path`, waiting for another process holding the lock.
+    pub(crate) fn acquire(dest_path: &Path) -> io::Result<Self> {
+        let lock_path = lock_path(dest_path)?;
+        if let Some(parent) = lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+
+        #[cfg(feature = "locking")]
+        let waited = match file.try_lock_exclusive() {
+            Ok(()) => false,
+            Err(ref e) if e.kind() == fs2::lock_contended_error().kind() => {
+                use log::info;
+
+                info!("Waiting for another download of {:?}...", dest_path);
+                file.lock_exclusive()?;
+                true
+            }
+            Err(e) => return Err(e),
+        };
+        #[cfg(not(feature = "locking"))]
+        let waited = false;
+
+        Ok(Self { file, waited })
+    }
+
+    /// Whether another process held the lock, and may have downloaded the artifact.
+    pub(crate) fn waited(&self) -> bool {
+        self.waited
+    }
+}
+
+/// Where the lock of `dest_path` is kept. Removing a lock file once it's released would let
+/// a process waiting for it lock the removed file, so they're kept in the cache directory,
+/// named after the digest of the canonical `dest_path`, instead of next to the artifacts.
+fn lock_path(dest_path: &Path) -> io::Result<PathBuf> {
+    use crate::{checksum, sibling_path};
+    use sha2::{Digest, Sha256};
+
+    let (cache, file_name) = match (dirs::cache_dir(), dest_path.file_name()) {
+        (Some(cache), Some(file_name)) => (cache, file_name),
+        _ => return Ok(sibling_path(dest_path, ".lock")),
+    };
+    let parent = match dest_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    let canonical = fs::canonicalize(parent)?.join(file_name);
+    let key = checksum::to_hex(&Sha256::digest(canonical.to_string_lossy().as_bytes()));
+
+    Ok(cache.join("just").join("locks").join(key))
+}
+
+#[cfg(feature = "locking")]
+impl Drop for DownloadLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
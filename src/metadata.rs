@@ -0,0 +1,112 @@
+//! Small documents fetched for verification, like signatures and provenance, cached by URL
+//! and revalidated with their ETag, so batches and repeated runs don't fetch them again.
+
+use crate::checksum;
+use crate::options::DownloadOptions;
+use just_core::result::BoxedResult;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// Documents larger than this are rejected, they're expected to be a few kilobytes.
+const MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The documents already fetched by this process, which aren't even revalidated.
+static FETCHED: Mutex<Option<HashMap<String, Option<Vec<u8>>>>> = Mutex::new(None);
+
+fn cache_path(url: &str) -> Option<PathBuf> {
+    use sha2::{Digest, Sha256};
+
+    let key = checksum::to_hex(&Sha256::digest(url.as_bytes()));
+    dirs::cache_dir().map(|cache| cache.join("just").join("metadata").join(key))
+}
+
+fn fetched() -> MutexGuard<'static, Option<HashMap<String, Option<Vec<u8>>>>> {
+    FETCHED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The document at `url`, or `None` if the server doesn't have it. Only documents and their
+/// absence are remembered, errors are not.
+pub(crate) fn get(
+    client: &Client,
+    url: &str,
+    options: &DownloadOptions,
+) -> BoxedResult<Option<Vec<u8>>> {
+    let known = fetched().get_or_insert_with(HashMap::new).get(url).cloned();
+    if let Some(document) = known {
+        return Ok(document);
+    }
+
+    let document = fetch(client, url, options)?;
+    fetched()
+        .get_or_insert_with(HashMap::new)
+        .insert(url.to_owned(), document.clone());
+
+    Ok(document)
+}
+
+fn fetch(client: &Client, url: &str, options: &DownloadOptions) -> BoxedResult<Option<Vec<u8>>> {
+    use crate::sibling_path;
+    use log::debug;
+    use reqwest::header::{ETAG, IF_NONE_MATCH};
+    use reqwest::StatusCode;
+    use std::fs;
+    use std::io::Read;
+
+    let path = cache_path(url);
+    let etag_path = path.as_ref().map(|path| sibling_path(path, ".etag"));
+    let cached_etag = etag_path
+        .as_ref()
+        .and_then(|etag_path| fs::read_to_string(etag_path).ok());
+
+    let mut request = client.get(url);
+    if let Some(etag) = cached_etag
+        .as_ref()
+        .filter(|_| path.as_ref().map_or(false, |path| path.exists()))
+    {
+        request = request.header(IF_NONE_MATCH, etag.trim());
+    }
+    let response = options.send(client, request)?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if let Some(path) = &path {
+            debug!("Reusing {} from {:?}", url, path);
+            return Ok(Some(fs::read(path)?));
+        }
+    }
+    // Only these say there's nothing published, a failing server mustn't pass for that.
+    if response.status() == StatusCode::NOT_FOUND || response.status() == StatusCode::GONE {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    if !response.status().is_success() {
+        return Err(format!("Unexpected status {} of {}", response.status(), url).into());
+    }
+
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|hv| hv.to_str().ok())
+        .map(str::to_owned);
+    let mut document = Vec::new();
+    response.take(MAX_SIZE + 1).read_to_end(&mut document)?;
+    if document.len() as u64 > MAX_SIZE {
+        return Err(format!("{} is larger than {} bytes", url, MAX_SIZE).into());
+    }
+
+    if let (Some(path), Some(etag_path), Some(etag)) = (&path, &etag_path, etag) {
+        let stored = path
+            .parent()
+            .map(fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| fs::write(path, &document))
+            .and_then(|_| fs::write(etag_path, etag));
+        if let Err(e) = stored {
+            debug!("Could not cache {} in {:?}: {}", url, path, e);
+        }
+    }
+
+    Ok(Some(document))
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Resolve,
+    Connect,
+    Transfer,
+    Verify,
+}
+
+/// Receives measurements of every download, e.g. to export them as Prometheus metrics.
+/// Every method does nothing by default.
+pub trait MetricsSink: Send + Sync {
+    fn bytes_transferred(&self, _package: &str, _bytes: u64) {}
+
+    fn phase_finished(&self, _package: &str, _phase: Phase, _duration: Duration) {}
+
+    /// Called before attempt number `attempt` (starting at 1) of a download that failed before.
+    fn retried(&self, _package: &str, _attempt: usize) {}
+
+    /// The local artifact was still up to date.
+    fn cache_hit(&self, _package: &str) {}
+
+    /// The speed averaged over `ProgressAppearance::speed_window`, about once a second.
+    fn speed(&self, _package: &str, _bytes_per_second: f64, _eta: Option<Duration>) {}
+}
+
+impl fmt::Debug for dyn MetricsSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MetricsSink")
+    }
+}
@@ -0,0 +1,83 @@
+//! Hooks into every outgoing request, e.g. for custom authentication schemes.
+
+use crate::har;
+use crate::options::DownloadOptions;
+use just_core::result::BoxedResult;
+use reqwest::{Client, Request, RequestBuilder, Response};
+use std::fmt;
+
+/// Inspects or modifies a request before it's sent. Returning a response answers the
+/// request with it instead of sending it, a response can be built from an `http::Response`.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: &mut Request) -> BoxedResult<Option<Response>>;
+}
+
+impl<F: Fn(&mut Request) -> BoxedResult<Option<Response>> + Send + Sync> Middleware for F {
+    fn handle(&self, request: &mut Request) -> BoxedResult<Option<Response>> {
+        self(request)
+    }
+}
+
+impl fmt::Debug for dyn Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Middleware")
+    }
+}
+
+/// Passes `request` through the middlewares of `options` in order and sends it, unless one
+/// of them answers it. The request is recorded if `options.har` asks for it.
+pub(crate) fn send(
+    client: &Client,
+    request: RequestBuilder,
+    options: &DownloadOptions,
+) -> BoxedResult<Response> {
+    use log::debug;
+    use std::time::{Instant, SystemTime};
+
+    let mut request = request.build()?;
+    for middleware in &options.middlewares {
+        if let Some(response) = middleware.handle(&mut request)? {
+            debug!(
+                "{} {} answered by a middleware",
+                request.method(),
+                request.url()
+            );
+            return Ok(response);
+        }
+    }
+
+    if options.log_http {
+        debug!("{} {}", request.method(), request.url());
+        for (name, value) in request.headers() {
+            debug!(
+                "> {}: {}",
+                name,
+                har::redacted(name, value.to_str().unwrap_or(""))
+            );
+        }
+    }
+    let recorded = options.har.as_ref().map(|_| har::request(&request));
+    let started = SystemTime::now();
+    let sending = Instant::now();
+    let response = client.execute(request);
+    if let (Some(recorder), Some(recorded)) = (&options.har, recorded) {
+        recorder.record(recorded, started, sending.elapsed(), &response);
+    }
+    if options.log_http {
+        match &response {
+            Ok(response) => {
+                debug!("{} from {}", response.status(), response.url());
+                for (name, value) in response.headers() {
+                    debug!(
+                        "< {}: {}",
+                        name,
+                        har::redacted(name, value.to_str().unwrap_or(""))
+                    );
+                }
+            }
+            Err(e) => debug!("Request failed after {:?}: {}", sending.elapsed(), e),
+        }
+    }
+
+    Ok(response?)
+}
@@ -0,0 +1,99 @@
+//! How fast and reliable mirrors have been, kept across runs to try the best one first.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Serializes updates of the statistics file by the threads of a batch.
+static STATS: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct MirrorStats {
+    bytes: u64,
+    seconds: f64,
+    successes: u64,
+    failures: u64,
+}
+
+impl MirrorStats {
+    /// The throughput weighted by the share of successful downloads.
+    fn score(&self) -> f64 {
+        let attempts = self.successes + self.failures;
+        if attempts == 0 || self.seconds <= 0.0 {
+            return 0.0;
+        }
+
+        self.bytes as f64 / self.seconds * self.successes as f64 / attempts as f64
+    }
+}
+
+fn stats_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache| cache.join("just").join("mirrors.json"))
+}
+
+fn load() -> HashMap<String, MirrorStats> {
+    use std::fs;
+
+    stats_path()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+fn host(url: &str) -> Option<String> {
+    use reqwest::Url;
+
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+}
+
+/// Orders `urls` by how well their hosts did before. Hosts without statistics keep their
+/// place in front, so they get measured.
+pub(crate) fn rank(urls: &mut [String]) {
+    let stats = load();
+    let score = |url: &String| {
+        host(url)
+            .and_then(|host| stats.get(&host))
+            .map(MirrorStats::score)
+            .unwrap_or(f64::INFINITY)
+    };
+
+    urls.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+/// Adds a download of `bytes` from `url` taking `duration`, or a failure if `None`.
+pub(crate) fn record(url: &str, transfer: Option<(u64, Duration)>) {
+    use log::debug;
+    use std::fs;
+
+    let (host, path) = match (host(url), stats_path()) {
+        (Some(host), Some(path)) => (host, path),
+        _ => return,
+    };
+
+    let _guard = STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut stats = load();
+    let entry = stats.entry(host).or_default();
+    match transfer {
+        Some((bytes, duration)) => {
+            entry.bytes += bytes;
+            entry.seconds += duration.as_secs_f64();
+            entry.successes += 1;
+        }
+        None => entry.failures += 1,
+    }
+
+    let written = path
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| fs::write(&path, serde_json::to_vec(&stats)?));
+    if let Err(e) = written {
+        debug!("Could not store mirror statistics in {:?}: {}", path, e);
+    }
+}
@@ -0,0 +1,835 @@
+use crate::artifact::ArtifactKind;
+#[cfg(feature = "blocking")]
+use crate::bandwidth::BandwidthLimiter;
+use crate::checksum::Checksum;
+use crate::discovery::VersionSource;
+use crate::error::DownloadError;
+use crate::events::{DownloadEvent, EventListener};
+use crate::extract::{ExtractLimits, Placement};
+use crate::filename;
+use crate::har::HarRecorder;
+use crate::hooks::{AfterDownload, BeforeDownload, Scanner};
+use crate::license::LicenseAcceptance;
+use crate::metrics::MetricsSink;
+use crate::middleware::{self, Middleware};
+use crate::provenance::ProvenancePolicy;
+#[cfg(feature = "blocking")]
+use crate::retry_budget::RetryBudget;
+use crate::signature::SignaturePolicy;
+#[cfg(feature = "blocking")]
+use crate::slots::TransferSlots;
+use crate::telemetry::TelemetrySink;
+#[cfg(feature = "tuf")]
+use crate::tuf::TufConfig;
+use crate::versions::{self, BuildMetadata, Prereleases, VersionScheme};
+use just_core::result::BoxedResult;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Proxy, RedirectPolicy, RequestBuilder, Response};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Handling of the macOS `com.apple.quarantine` attribute and the Windows `Zone.Identifier`
+/// stream (the Mark of the Web), which make Gatekeeper and SmartScreen prompt before the
+/// artifact is run for the first time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quarantine {
+    /// Leaves the attribute as it is.
+    Keep,
+    /// Removes the attribute so non-interactive installs aren't interrupted.
+    Remove,
+    /// Explicitly sets the attribute.
+    Set,
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine::Keep
+    }
+}
+
+/// Whether speeds are shown in bytes (`MB/s`) or bits (`Mbit/s`) per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RateUnit {
+    Bytes,
+    Bits,
+}
+
+impl Default for RateUnit {
+    fn default() -> Self {
+        RateUnit::Bytes
+    }
+}
+
+/// Whether units are scaled by 1024 (`MiB`) or by 1000 (`MB`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitPrefix {
+    Binary,
+    Decimal,
+}
+
+impl Default for UnitPrefix {
+    fn default() -> Self {
+        UnitPrefix::Binary
+    }
+}
+
+/// The look of the progress bar, see indicatif's `ProgressStyle` for the template syntax.
+/// Only drawn with the `progress` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressAppearance {
+    pub template: String,
+    pub progress_chars: String,
+    /// The template of the spinner drawn instead of the bar if the size isn't known.
+    pub spinner_template: String,
+    /// Redraws per second, indicatif's default if not given.
+    pub refresh_rate: Option<u64>,
+    /// Averages speed and ETA over this window and shows them as `{msg}`, which is smoother
+    /// than indicatif's `{bytes_per_sec}` and `{eta}`. Also reported to `MetricsSink::speed`.
+    pub speed_window: Option<Duration>,
+    /// The unit of the averaged speed.
+    pub rate_unit: RateUnit,
+    pub unit_prefix: UnitPrefix,
+}
+
+impl Default for ProgressAppearance {
+    fn default() -> Self {
+        Self {
+            template: "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})".to_owned(),
+            progress_chars: "=>".to_owned(),
+            spinner_template: "{spinner:.green} [{elapsed_precise}] {bytes} {msg}".to_owned(),
+            refresh_rate: None,
+            speed_window: None,
+            rate_unit: RateUnit::default(),
+            unit_prefix: UnitPrefix::default(),
+        }
+    }
+}
+
+/// What happens if the destination of an artifact exists already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Replaces the existing file.
+    Overwrite,
+    /// Keeps and reports the existing file without downloading.
+    Skip,
+    /// Stores the artifact under a new name, e.g. `tool-1.zip`.
+    Rename,
+    /// Fails with a `DownloadError::Failed` caused by `DownloadError::AlreadyExists`.
+    Error,
+    /// Keeps and reports the existing file if it matches the checksum, or the size the
+    /// server announces without one, and downloads it again otherwise.
+    Reuse,
+}
+
+impl Default for CollisionPolicy {
+    fn default() -> Self {
+        CollisionPolicy::Reuse
+    }
+}
+
+/// Whether the destination of an artifact carries its version, so several versions of a
+/// package can be kept side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionedDestination {
+    /// `node.tar.gz`
+    Off,
+    /// `node-18.19.0.tar.gz`, unless the file name contains the version already.
+    FileName,
+    /// `18.19.0/node.tar.gz`
+    Directory,
+}
+
+impl Default for VersionedDestination {
+    fn default() -> Self {
+        VersionedDestination::Off
+    }
+}
+
+/// Settings for every request to a host, e.g. from a `[host."artifacts.corp"]` section
+/// of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HostOptions {
+    /// Sent as bearer token, taking precedence over `username` and `password`.
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Replaces `DownloadOptions::proxy` for the host.
+    pub proxy: Option<String>,
+    /// Idle connections kept open to the host for later requests. Doesn't limit how many
+    /// connections are open at the same time.
+    pub max_idle_connections: Option<usize>,
+    /// Seconds a request to the host may take.
+    pub timeout: Option<u64>,
+    /// Replaces `DownloadOptions::accept_invalid_certs` for the host.
+    pub accept_invalid_certs: Option<bool>,
+    /// A PEM file with an additional trusted root certificate, e.g. of an internal CA.
+    pub ca_certificate: Option<PathBuf>,
+}
+
+impl HostOptions {
+    fn authorization(&self) -> Option<String> {
+        match (&self.token, &self.username) {
+            (Some(token), _) => Some(format!("Bearer {}", token)),
+            (None, Some(username)) => {
+                let credentials = format!(
+                    "{}:{}",
+                    username,
+                    self.password.as_deref().unwrap_or_default()
+                );
+                Some(format!("Basic {}", base64::encode(credentials)))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Tunes how `download_with_options` fetches an artifact.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    /// Whether pre-releases are considered when resolving the version.
+    pub prereleases: Prereleases,
+    /// The long-term support line, e.g. `^18`, `VersionSelector::Lts` picks from.
+    pub lts: Option<VersionReq>,
+    /// Rejects resolving to older versions, e.g. ones with known vulnerabilities.
+    /// Only an option for now: a manifest field for it is deferred until just-core's
+    /// `Package` has one.
+    pub minimum_version: Option<Version>,
+    /// Versions which are known to be broken or vulnerable, skipped unless asked for exactly.
+    pub yanked: Vec<Version>,
+    /// Orders listed versions which aren't semver, the last listed one is picked without it.
+    pub version_scheme: Option<Arc<dyn VersionScheme>>,
+    /// How build metadata of the version is put into the URL.
+    pub build_metadata: BuildMetadata,
+    /// Where versions are looked up if the manifest doesn't list them. Manifests can't
+    /// declare a source themselves yet, that's deferred until just-core's `Package` has a
+    /// field for it.
+    pub version_source: Option<VersionSource>,
+    /// How long looked up versions are reused, an hour if not given.
+    pub version_cache_ttl: Option<Duration>,
+    /// Content-Types the response has to match, e.g. `application/zip`.
+    /// Every Content-Type is accepted when empty.
+    pub expected_content_types: Vec<String>,
+    /// Keeps the local modification time instead of applying `Last-Modified`.
+    pub ignore_last_modified: bool,
+    /// Only transfers the artifact if the remote one is newer than the local one.
+    pub if_newer: bool,
+    /// Deletes the destination and what's left of interrupted downloads of it before
+    /// downloading, e.g. if the local artifact is suspected to be corrupted. Takes
+    /// precedence over `collision` and `if_newer`.
+    pub force: bool,
+    /// Marks the artifact as executable (Unix only), for raw binaries that are not archives.
+    pub make_executable: bool,
+    /// Controls the `com.apple.quarantine` attribute on macOS and the `Zone.Identifier`
+    /// stream on Windows.
+    pub quarantine: Quarantine,
+    /// Limits applied when unpacking the artifact.
+    pub extract_limits: ExtractLimits,
+    /// How an artifact that isn't unpacked is put into the target directory.
+    pub placement: Placement,
+    /// The name a bare executable (one that isn't an archive) is placed under.
+    pub binary_name: Option<String>,
+    /// The declared kind of artifact, which is verified after the download.
+    /// Detected from the downloaded file when not given.
+    pub artifact_kind: Option<ArtifactKind>,
+    /// Unpacks the squashfs embedded into an AppImage into `squashfs-root`.
+    pub extract_appimage: bool,
+    /// The digest the artifact has to match.
+    pub checksum: Option<Checksum>,
+    /// Digests by version, like a manifest's `[download.checksums]` table, taking
+    /// precedence over `checksum` for the version that's downloaded.
+    pub checksums: HashMap<String, Checksum>,
+    /// Accepts sha1 and md5 checksums, which aren't collision resistant.
+    pub allow_insecure_digests: bool,
+    /// URL templates replacing the manifest's one for the versions matching the requirement,
+    /// e.g. for artifacts named differently before `2.0`. The first match is used.
+    pub url_overrides: Vec<(VersionReq, String)>,
+    /// Alternative URL templates (with `{version}` or `{raw_version}`), tried in order after
+    /// the manifest's one.
+    pub mirrors: Vec<String>,
+    /// The region served best by a mirror, e.g. `cn`, keyed by its URL template.
+    pub mirror_regions: HashMap<String, String>,
+    /// Mirrors of this region are tried before the manifest's URL and the other mirrors.
+    pub region: Option<String>,
+    /// Downloads a snapshot of the Internet Archive once the manifest's URL and the mirrors
+    /// failed. Only done if the artifact can be verified by a checksum, signature or provenance.
+    pub archive_fallback: bool,
+    /// How often an artifact failing verification is downloaded again, moving on to the
+    /// next mirror each time.
+    pub verification_retries: usize,
+    /// Requires SLSA provenance for the artifact satisfying this policy.
+    pub provenance: Option<ProvenancePolicy>,
+    /// Where the SBOM (SPDX or CycloneDX) of the artifact is published, may contain `{version}`.
+    pub sbom_url: Option<String>,
+    /// Saves the release notes next to the artifact, from `changelog_url` or else the body of
+    /// the GitHub release of the version.
+    pub changelog: bool,
+    /// Where the release notes are published, may contain `{version}`.
+    pub changelog_url: Option<String>,
+    /// Where the license to accept before downloading is published, may contain `{version}`.
+    pub license_url: Option<String>,
+    /// Accepts the license at `license_url`. Without it, such licenses are never accepted.
+    pub license_acceptance: Option<Arc<dyn LicenseAcceptance>>,
+    /// Looks for a companion signature and verifies the artifact with it.
+    pub signatures: Option<SignaturePolicy>,
+    /// Receives bytes, timings, retries and cache hits.
+    pub metrics: Option<Arc<dyn MetricsSink>>,
+    /// Is told about every step of the download, see `events::channel` for a channel.
+    pub events: Option<Arc<dyn EventListener>>,
+    /// Receives pseudonymized statistics of every download, nothing is reported without it.
+    pub telemetry: Option<Arc<dyn TelemetrySink>>,
+    /// Called once the download is resolved, and may cancel it.
+    pub before_download: Option<Arc<dyn BeforeDownload>>,
+    /// Called with the outcome of every download.
+    pub after_download: Option<Arc<dyn AfterDownload>>,
+    /// Scans every artifact after it has been verified, and may reject it.
+    pub scanner: Option<Arc<dyn Scanner>>,
+    /// Applied in order to every request.
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+    /// Records every request sent, for bug reports.
+    pub har: Option<Arc<HarRecorder>>,
+    /// Stores the artifact under the name the server suggests with `Content-Disposition`,
+    /// including RFC 5987 `filename*`, instead of the one in the manifest's URL. The name is
+    /// asked for with a HEAD request before anything is decided by the destination.
+    pub content_disposition: bool,
+    /// Logs the headers of every request and response (without credentials), redirects
+    /// and the TLS settings at debug level, for diagnosing servers and mirrors.
+    pub log_http: bool,
+    pub progress: ProgressAppearance,
+    /// Hides the progress bar and only logs the start and the completion of the download.
+    pub quiet: bool,
+    /// Verifies the artifact against a TUF repository instead of `checksum`.
+    #[cfg(feature = "tuf")]
+    pub tuf: Option<TufConfig>,
+    /// The directory artifacts are stored in. If not given, they're stored in the user's
+    /// cache directory, e.g. `~/.cache/just/downloads/<package>/<version>/`, or the current
+    /// directory if there is none.
+    pub cache_dir: Option<PathBuf>,
+    /// Puts the version into the destination of the artifact.
+    pub versioned_destination: VersionedDestination,
+    /// Flushes the artifact and its directory to disk before reporting the download as
+    /// complete, so a power loss can't leave a truncated artifact behind.
+    pub durable: bool,
+    /// What happens if the destination exists already, unless downloading `if_newer`.
+    pub collision: CollisionPolicy,
+    /// Proxy for all requests, e.g. `http://proxy.corp:3128`.
+    pub proxy: Option<String>,
+    /// How often a download failing because of network or disk errors is attempted again.
+    pub retries: usize,
+    /// Cancels the downloads once passed, which fail with `DownloadError::TimedOut`.
+    /// Downloads which haven't started by then fail right away.
+    pub deadline: Option<Instant>,
+    /// Caps the retries of every download sharing it, on top of `retries`.
+    #[cfg(feature = "blocking")]
+    pub retry_budget: Option<Arc<RetryBudget>>,
+    /// Downloads artifacts of servers accepting range requests in this many concurrent
+    /// segments, written into a sparse file. Off below 2, and not limited by
+    /// `max_bytes_per_second`. Segments are requested with `If-Range`, so the download starts
+    /// over if the artifact changes in between. That's the only resumption: the `.part` file
+    /// of an interrupted download isn't resumed but downloaded again.
+    pub segments: usize,
+    /// Downloads chunks of an artifact from the `mirrors` at the same time as from the
+    /// manifest's URL, so faster servers transfer more of it. Needs range requests, and a
+    /// checksum, signature or provenance to verify the assembled artifact with. Only mirrors
+    /// announcing the same size and a validator for `If-Range` take part.
+    pub swarm: bool,
+    /// Keeps the throughput and failures of the manifest's URL and the `mirrors` in the cache
+    /// directory, and tries them in the order of how well they did instead of as listed.
+    pub mirror_stats: bool,
+    /// Writes segmented downloads through a memory map instead of a write per chunk.
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+    /// Caps the transfer rate of the download.
+    pub max_bytes_per_second: Option<u64>,
+    /// Caps the aggregate transfer rate of every download sharing the limiter, including
+    /// the segments of segmented downloads.
+    #[cfg(feature = "blocking")]
+    pub bandwidth: Option<Arc<BandwidthLimiter>>,
+    /// Caps the transfers of every batch sharing the slots, pausing the ones of a lower
+    /// priority for the higher ones. Set by `DownloadManager::new`.
+    #[cfg(feature = "blocking")]
+    pub transfer_slots: Option<Arc<TransferSlots>>,
+    /// Requests the batch APIs make at the same time, a default if 0.
+    pub concurrency: usize,
+    /// Fails downloads without a checksum instead of warning about them.
+    pub require_checksum: bool,
+    /// Only uses artifacts already present in the destination, never touching the network.
+    pub offline: bool,
+    /// Accepts invalid TLS certificates, e.g. of servers behind intercepting proxies.
+    pub accept_invalid_certs: bool,
+    /// Asks for a gzip transfer encoding and undoes it, which needs the `archives` feature.
+    /// Off by default, so a `.tar.gz` is stored as served and matches its Content-Length.
+    pub decode_content: bool,
+    /// Settings for requests to a host (and its subdomains), keyed by the host name.
+    pub hosts: HashMap<String, HostOptions>,
+}
+
+/// Follows up to 10 redirects like reqwest does by default, logging every hop.
+fn logged_redirects() -> RedirectPolicy {
+    use log::debug;
+
+    RedirectPolicy::custom(|attempt| {
+        debug!(
+            "{} redirects from {} to {}",
+            attempt.status(),
+            attempt.previous().last().map_or("", |url| url.as_str()),
+            attempt.url()
+        );
+        if attempt.previous().len() > 10 {
+            attempt.too_many_redirects()
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// What the blocking and the async `ClientBuilder` of reqwest have in common, so clients of
+/// either are built with the same settings.
+pub(crate) trait Builder: Sized {
+    fn gzip(self, enable: bool) -> Self;
+    fn proxy(self, proxy: Proxy) -> Self;
+    fn redirect(self, policy: RedirectPolicy) -> Self;
+    fn default_headers(self, headers: HeaderMap) -> Self;
+    fn max_idle_per_host(self, max: usize) -> Self;
+    fn timeout(self, timeout: Duration) -> Self;
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    fn danger_accept_invalid_certs(self, accept: bool) -> Self;
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    fn add_root_certificate(self, certificate: reqwest::Certificate) -> Self;
+}
+
+macro_rules! impl_builder {
+    ($builder:ty) => {
+        impl Builder for $builder {
+            fn gzip(self, enable: bool) -> Self {
+                <$builder>::gzip(self, enable)
+            }
+
+            fn proxy(self, proxy: Proxy) -> Self {
+                <$builder>::proxy(self, proxy)
+            }
+
+            fn redirect(self, policy: RedirectPolicy) -> Self {
+                <$builder>::redirect(self, policy)
+            }
+
+            fn default_headers(self, headers: HeaderMap) -> Self {
+                <$builder>::default_headers(self, headers)
+            }
+
+            fn max_idle_per_host(self, max: usize) -> Self {
+                <$builder>::max_idle_per_host(self, max)
+            }
+
+            fn timeout(self, timeout: Duration) -> Self {
+                <$builder>::timeout(self, timeout)
+            }
+
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            fn danger_accept_invalid_certs(self, accept: bool) -> Self {
+                <$builder>::danger_accept_invalid_certs(self, accept)
+            }
+
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            fn add_root_certificate(self, certificate: reqwest::Certificate) -> Self {
+                <$builder>::add_root_certificate(self, certificate)
+            }
+        }
+    };
+}
+
+impl_builder!(reqwest::ClientBuilder);
+#[cfg(feature = "async")]
+impl_builder!(reqwest::r#async::ClientBuilder);
+
+impl DownloadOptions {
+    /// The client for requests to the host of `url`.
+    pub(crate) fn client(&self, url: &str) -> BoxedResult<Client> {
+        Ok(self.configure(Client::builder(), url)?.build()?)
+    }
+
+    /// Applies the proxy, credentials, certificates and timeout for requests to the host of
+    /// `url` to `builder`.
+    pub(crate) fn configure<B: Builder>(&self, builder: B, url: &str) -> BoxedResult<B> {
+        use reqwest::header::{HeaderValue, AUTHORIZATION};
+
+        let host = self.host(url);
+        // Transfer encodings are undone by `fetch` if `decode_content` asks for it.
+        let mut builder = builder.gzip(false);
+        let proxy = host
+            .and_then(|host| host.proxy.as_ref())
+            .or_else(|| self.proxy.as_ref());
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        builder = self.tls(builder, host)?;
+        if self.log_http {
+            builder = builder.redirect(logged_redirects());
+        }
+
+        if let Some(host) = host {
+            if let Some(authorization) = host.authorization() {
+                let mut headers = HeaderMap::new();
+                headers.insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+                builder = builder.default_headers(headers);
+            }
+            if let Some(max_idle_connections) = host.max_idle_connections {
+                builder = builder.max_idle_per_host(max_idle_connections);
+            }
+        }
+        let timeout = host
+            .and_then(|host| host.timeout)
+            .map(Duration::from_secs)
+            .into_iter()
+            .chain(self.remaining())
+            .min();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        Ok(builder)
+    }
+
+    /// Sends `request` with `client` through the middlewares, and records it if asked to.
+    pub(crate) fn send(&self, client: &Client, request: RequestBuilder) -> BoxedResult<Response> {
+        middleware::send(client, request, self)
+    }
+
+    /// The certificate settings for `host`.
+    fn tls<B: Builder>(&self, builder: B, host: Option<&HostOptions>) -> BoxedResult<B> {
+        let accept_invalid_certs = host
+            .and_then(|host| host.accept_invalid_certs)
+            .unwrap_or(self.accept_invalid_certs);
+        let ca_certificate = host.and_then(|host| host.ca_certificate.as_ref());
+        #[cfg(any(feature = "native-tls", feature = "rustls"))]
+        let builder = {
+            use reqwest::Certificate;
+            use std::fs;
+
+            let mut builder = builder.danger_accept_invalid_certs(accept_invalid_certs);
+            if let Some(ca_certificate) = ca_certificate {
+                builder = builder
+                    .add_root_certificate(Certificate::from_pem(&fs::read(ca_certificate)?)?);
+            }
+            builder
+        };
+        #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+        {
+            if accept_invalid_certs || ca_certificate.is_some() {
+                return Err(
+                    "Certificate settings require the `native-tls` or `rustls` feature".into(),
+                );
+            }
+        }
+        if self.log_http {
+            use log::debug;
+
+            // reqwest doesn't tell the negotiated protocol and cipher, only what's configured.
+            // With both features, reqwest picks the platform's TLS.
+            let backend = if cfg!(feature = "native-tls") {
+                "the platform's TLS"
+            } else if cfg!(feature = "rustls") {
+                "rustls"
+            } else {
+                "no TLS, so HTTP only"
+            };
+            debug!(
+                "TLS with {}, trusting {}{}",
+                backend,
+                ca_certificate.map_or("the system's certificates".to_owned(), |path| format!(
+                    "{:?} in addition",
+                    path
+                )),
+                if accept_invalid_certs {
+                    ", accepting invalid certificates"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        Ok(builder)
+    }
+
+    /// The URLs to try for `version`: the mirrors of the preferred `region`, `download_url`
+    /// and the other mirrors.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn ordered_urls(
+        &self,
+        download_url: &str,
+        raw_version: &str,
+    ) -> Result<Vec<String>, DownloadError> {
+        let in_region = |mirror: &&String| match (&self.region, self.mirror_regions.get(*mirror)) {
+            (Some(preferred), Some(region)) => region.eq_ignore_ascii_case(preferred),
+            _ => false,
+        };
+        let (regional, others): (Vec<&String>, Vec<&String>) =
+            self.mirrors.iter().partition(in_region);
+        let url = |mirror: &String| mirror_url(mirror, raw_version);
+
+        regional
+            .into_iter()
+            .map(url)
+            .chain(Some(Ok(download_url.to_owned())))
+            .chain(others.into_iter().map(url))
+            .collect()
+    }
+
+    /// Whether the artifact is checked against something else than the server serving it.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn verifies(&self) -> bool {
+        self.checksum.is_some() || self.signatures.is_some() || self.provenance.is_some()
+    }
+
+    /// The URLs of the `mirrors` for the version put into URLs as `raw_version`.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn mirror_urls(&self, raw_version: &str) -> Result<Vec<String>, DownloadError> {
+        self.mirrors
+            .iter()
+            .map(|mirror| mirror_url(mirror, raw_version))
+            .collect()
+    }
+
+    /// The most specific `hosts` entry matching the host of `url`.
+    fn host(&self, url: &str) -> Option<&HostOptions> {
+        use reqwest::Url;
+
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.to_ascii_lowercase();
+                host == name || host.ends_with(&format!(".{}", name))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, options)| options)
+    }
+
+    pub(crate) fn is_yanked(&self, version: &Version) -> bool {
+        self.yanked.contains(version)
+    }
+
+    /// The entry of `url_overrides` for `version`.
+    pub(crate) fn url_template_for(&self, version: &Version) -> Option<&str> {
+        self.url_overrides
+            .iter()
+            .find(|(req, _)| req.matches(version))
+            .map(|(_, template)| template.as_str())
+    }
+
+    /// The entry of `checksums` for `version`, which may be listed with a `v` prefix.
+    pub(crate) fn checksum_for(&self, version: &Version) -> Option<&Checksum> {
+        let version = version.to_string();
+
+        self.checksums
+            .iter()
+            .find(|(listed, _)| versions::strip_prefix(listed) == version)
+            .map(|(_, checksum)| checksum)
+    }
+
+    /// Where version `version` of an artifact of `package` named `file_name` is stored.
+    pub(crate) fn destination(
+        &self,
+        file_name: &Path,
+        package: &str,
+        version: &Version,
+    ) -> PathBuf {
+        let file_name = match self.versioned_destination {
+            VersionedDestination::Off => file_name.to_owned(),
+            VersionedDestination::FileName => versioned_file_name(file_name, version),
+            VersionedDestination::Directory => Path::new(&version.to_string()).join(file_name),
+        };
+
+        match &self.cache_dir {
+            Some(cache_dir) => cache_dir.join(file_name),
+            None => match dirs::cache_dir() {
+                // Every version has a directory of its own already.
+                Some(cache_dir) => cache_dir
+                    .join("just")
+                    .join("downloads")
+                    .join(filename::path_component(package))
+                    .join(version.to_string())
+                    .join(file_name.file_name().unwrap_or(file_name.as_os_str())),
+                None => file_name,
+            },
+        }
+    }
+
+    #[cfg(all(feature = "blocking", feature = "mmap"))]
+    pub(crate) fn use_mmap(&self) -> bool {
+        self.mmap
+    }
+
+    #[cfg(all(feature = "blocking", not(feature = "mmap")))]
+    pub(crate) fn use_mmap(&self) -> bool {
+        false
+    }
+
+    /// The level of log lines in between the start and the completion of the download.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn detail_level(&self) -> log::Level {
+        if self.quiet {
+            log::Level::Debug
+        } else {
+            log::Level::Info
+        }
+    }
+
+    /// The time left until the deadline.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub(crate) fn deadline_passed(&self) -> bool {
+        self.remaining() == Some(Duration::from_secs(0))
+    }
+
+    /// Fails with `DownloadError::TimedOut` once the deadline has passed.
+    pub(crate) fn check_deadline(&self) -> BoxedResult<()> {
+        if self.deadline_passed() {
+            Err(DownloadError::TimedOut.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn emit<F: FnOnce() -> DownloadEvent>(&self, event: F) {
+        if let Some(events) = &self.events {
+            events.on_event(&event());
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn record<F: FnOnce(&dyn MetricsSink)>(&self, f: F) {
+        if let Some(metrics) = &self.metrics {
+            f(metrics.as_ref());
+        }
+    }
+
+    pub(crate) fn accepts_content_type(&self, content_type: Option<&str>) -> bool {
+        if self.expected_content_types.is_empty() {
+            return true;
+        }
+
+        let mime = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|mime| mime.trim().to_ascii_lowercase());
+
+        mime.map(|mime| {
+            self.expected_content_types
+                .iter()
+                .any(|expected| expected.eq_ignore_ascii_case(&mime))
+        })
+        .unwrap_or(false)
+    }
+}
+
+/// Fills the URL template of a mirror like the manifest's.
+#[cfg(feature = "blocking")]
+fn mirror_url(template: &str, raw_version: &str) -> Result<String, DownloadError> {
+    let url = versions::substitute(template, raw_version);
+    versions::check_substituted(&url)?;
+
+    Ok(url)
+}
+
+/// Inserts the version in front of the extension, e.g. `node.tar.gz` becomes
+/// `node-18.19.0.tar.gz`.
+fn versioned_file_name(file_name: &Path, version: &Version) -> PathBuf {
+    let version = version.to_string();
+    if file_name.to_string_lossy().contains(version.as_str()) {
+        return file_name.to_owned();
+    }
+
+    suffixed(file_name, &version)
+}
+
+/// Appends `-suffix` to the file name in front of the extension, keeping extensions like
+/// `.tar.gz` whole, e.g. `tool.tar.gz` becomes `tool-1.tar.gz`.
+pub(crate) fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lower = name.to_ascii_lowercase();
+    let stem_len = [".tar.gz", ".tar.xz", ".tar.zst", ".tar.bz2"]
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map(|extension| name.len() - extension.len())
+        .or_else(|| name.rfind('.').filter(|dot| *dot > 0))
+        .unwrap_or_else(|| name.len());
+
+    path.with_file_name(format!(
+        "{}-{}{}",
+        &name[..stem_len],
+        suffix,
+        &name[stem_len..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixes_in_front_of_the_extension() {
+        let suffixed = |path: &str| suffixed(Path::new(path), "1");
+        assert_eq!(suffixed("dir/tool.zip"), Path::new("dir/tool-1.zip"));
+        assert_eq!(suffixed("tool.TAR.GZ"), Path::new("tool-1.TAR.GZ"));
+        assert_eq!(
+            suffixed("tool.1.2.tar.zst"),
+            Path::new("tool.1.2-1.tar.zst")
+        );
+        assert_eq!(suffixed("tool"), Path::new("tool-1"));
+        assert_eq!(suffixed(".tool"), Path::new(".tool-1"));
+    }
+
+    #[test]
+    fn matches_hosts_and_their_subdomains() {
+        let host = |token: &str| HostOptions {
+            token: Some(token.to_owned()),
+            ..HostOptions::default()
+        };
+        let mut options = DownloadOptions::default();
+        options
+            .hosts
+            .insert("Corp.example".to_owned(), host("corp"));
+        options
+            .hosts
+            .insert("artifacts.corp.example".to_owned(), host("artifacts"));
+
+        let token = |url: &str| options.host(url).and_then(|host| host.token.as_deref());
+        assert_eq!(token("https://corp.example/tool.zip"), Some("corp"));
+        assert_eq!(token("https://cdn.CORP.example/tool.zip"), Some("corp"));
+        assert_eq!(
+            token("https://eu.artifacts.corp.example/tool.zip"),
+            Some("artifacts")
+        );
+        assert_eq!(token("https://notcorp.example/tool.zip"), None);
+        assert_eq!(token("not a url"), None);
+    }
+
+    #[test]
+    fn prefers_tokens_over_basic_auth() {
+        let mut host = HostOptions {
+            username: Some("user".to_owned()),
+            password: Some("secret".to_owned()),
+            ..HostOptions::default()
+        };
+        assert_eq!(
+            host.authorization().as_deref(),
+            Some("Basic dXNlcjpzZWNyZXQ=")
+        );
+
+        host.token = Some("abc".to_owned());
+        assert_eq!(host.authorization().as_deref(), Some("Bearer abc"));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::extract::Placement;
+use crate::options::Quarantine;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+pub(crate) fn make_executable(path: &Path) -> io::Result<()> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn apply_quarantine(path: &Path, _url: &str, quarantine: Quarantine) -> io::Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const ATTRIBUTE: &str = "com.apple.quarantine";
+
+    match quarantine {
+        Quarantine::Keep => Ok(()),
+        Quarantine::Remove => {
+            if xattr::get(path, ATTRIBUTE)?.is_some() {
+                xattr::remove(path, ATTRIBUTE)?;
+            }
+
+            Ok(())
+        }
+        Quarantine::Set => {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            let value = format!("0081;{:x};just;", timestamp);
+
+            xattr::set(path, ATTRIBUTE, value.as_bytes())
+        }
+    }
+}
+
+/// Writes or removes the `Zone.Identifier` alternate data stream, marking the artifact as
+/// downloaded from the internet.
+#[cfg(windows)]
+pub(crate) fn apply_quarantine(path: &Path, url: &str, quarantine: Quarantine) -> io::Result<()> {
+    use std::ffi::OsString;
+    use std::fs;
+
+    let mut stream = OsString::from(path.as_os_str());
+    stream.push(":Zone.Identifier");
+
+    match quarantine {
+        Quarantine::Keep => Ok(()),
+        Quarantine::Remove => match fs::remove_file(&stream) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+        Quarantine::Set => {
+            // Zone 3 is the internet zone.
+            let value = format!("[ZoneTransfer]\r\nZoneId=3\r\nHostUrl={}\r\n", url);
+
+            fs::write(&stream, value)
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+pub(crate) fn apply_quarantine(
+    _path: &Path,
+    _url: &str,
+    _quarantine: Quarantine,
+) -> io::Result<()> {
+    Ok(())
+}
+
+/// Moves `from` onto `to`. Returns whether the replacement waits for the next reboot.
+#[cfg(not(windows))]
+pub(crate) fn replace(from: &Path, to: &Path) -> io::Result<bool> {
+    std::fs::rename(from, to).map(|()| false)
+}
+
+/// Moves `from` onto `to`. A destination that is locked because the tool is currently
+/// running is renamed aside first, which Windows allows for running executables.
+/// If that fails as well, the replacement is scheduled for the next reboot, and `true`
+/// returned. Other errors are returned as they are.
+#[cfg(windows)]
+pub(crate) fn replace(from: &Path, to: &Path) -> io::Result<bool> {
+    use crate::sibling_path;
+    use log::warn;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+
+    const ATTEMPTS: u64 = 5;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    let locked = |e: &io::Error| {
+        matches!(
+            e.raw_os_error(),
+            Some(ERROR_ACCESS_DENIED) | Some(ERROR_SHARING_VIOLATION)
+        )
+    };
+
+    let mut last_error = None;
+    for attempt in 1..=ATTEMPTS {
+        match fs::rename(from, to) {
+            Ok(()) => return Ok(false),
+            Err(e) if !locked(&e) => return Err(e),
+            Err(e) => {
+                let aside = sibling_path(to, ".old");
+                let _ = fs::remove_file(&aside);
+                if to.exists() && fs::rename(to, &aside).is_ok() {
+                    warn!("Moved locked {:?} aside to {:?}", to, aside);
+                    let _ = move_on_reboot(&aside, None);
+                } else {
+                    thread::sleep(Duration::from_millis(100 * attempt));
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+
+    warn!("{:?} is locked, it will be replaced on the next reboot", to);
+    move_on_reboot(from, Some(to))
+        .map(|()| true)
+        .map_err(|e| last_error.unwrap_or(e))
+}
+
+#[cfg(windows)]
+fn move_on_reboot(from: &Path, to: Option<&Path>) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use winapi::um::winbase::{
+        MoveFileExW, MOVEFILE_DELAY_UNTIL_REBOOT, MOVEFILE_REPLACE_EXISTING,
+    };
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    let from = wide(from);
+    let to = to.map(wide);
+    // Without a destination the file is deleted on reboot instead.
+    let (to_ptr, flags) = match to {
+        Some(ref to) => (
+            to.as_ptr(),
+            MOVEFILE_DELAY_UNTIL_REBOOT | MOVEFILE_REPLACE_EXISTING,
+        ),
+        None => (ptr::null(), MOVEFILE_DELAY_UNTIL_REBOOT),
+    };
+
+    let moved = unsafe { MoveFileExW(from.as_ptr(), to_ptr, flags) };
+
+    if moved == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Unix file systems create sparse files by extending them, which `set_len` does.
+#[cfg(all(feature = "blocking", not(windows)))]
+pub(crate) fn make_sparse(_file: &std::fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Marks `file` as sparse, which NTFS requires before it leaves unwritten ranges unallocated.
+#[cfg(all(feature = "blocking", windows))]
+pub(crate) fn make_sparse(file: &std::fs::File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winioctl::FSCTL_SET_SPARSE;
+
+    let mut returned = 0;
+    let marked = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle() as _,
+            FSCTL_SET_SPARSE,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+
+    if marked == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Flushes the directory entry of `path` to disk, so a rename onto it survives a crash.
+#[cfg(unix)]
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    use std::fs::File;
+
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+/// Windows doesn't allow opening directories to flush them, renames are journaled by NTFS.
+#[cfg(not(unix))]
+pub(crate) fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Puts the file `from` at `to`, replacing whatever is there.
+pub(crate) fn materialize(from: &Path, to: &Path, placement: Placement) -> io::Result<()> {
+    use log::debug;
+    use std::fs;
+
+    if placement != Placement::Copy && to.exists() {
+        fs::remove_file(to)?;
+    }
+    let result = match placement {
+        Placement::Copy => return fs::copy(from, to).map(|_| ()),
+        Placement::HardLink => fs::hard_link(from, to),
+        Placement::Clone => clone_file(from, to),
+    };
+
+    result.or_else(|e| {
+        debug!(
+            "Could not {:?} {:?} to {:?}, copying: {}",
+            placement, from, to, e
+        );
+        fs::copy(from, to).map(|_| ())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::fs::{self, File};
+    use std::os::unix::io::AsRawFd;
+
+    let source = File::open(from)?;
+    let dest = File::create(to)?;
+    if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let e = io::Error::last_os_error();
+        drop(dest);
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+
+    fs::set_permissions(to, source.metadata()?.permissions())
+}
+
+#[cfg(target_os = "macos")]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (from, to) = (c_path(from)?, c_path(to)?);
+
+    if unsafe { libc::clonefile(from.as_ptr(), to.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn clone_file(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Copy-on-write clones aren't supported on this platform",
+    ))
+}
+
+#[cfg(not(windows))]
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+/// Turns `path` into a verbatim (`\\?\`) path, which isn't limited to `MAX_PATH`. It's
+/// joined to the current directory and its `.` and `..` resolved, as Windows doesn't do
+/// that for verbatim paths, without requiring it to exist. Device paths and paths already
+/// verbatim are left as they are.
+#[cfg(windows)]
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    use std::env;
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let absolute = match env::current_dir() {
+        Ok(current_dir) => current_dir.join(path),
+        Err(_) => return path.to_owned(),
+    };
+
+    let mut components = absolute.components();
+    let mut verbatim = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                PathBuf::from(unc)
+            }
+            _ => return path.to_owned(),
+        },
+        _ => return path.to_owned(),
+    };
+    for component in components {
+        match component {
+            Component::Normal(name) => verbatim.push(name),
+            // Stops at the root, like `..` of the root does.
+            Component::ParentDir => {
+                verbatim.pop();
+            }
+            _ => {}
+        }
+    }
+
+    verbatim
+}
+
+/// Lets the AppImage runtime unpack its embedded squashfs into `target/squashfs-root`.
+#[cfg(feature = "blocking")]
+pub(crate) fn extract_appimage(appimage: &Path, target: &Path) -> io::Result<()> {
+    use std::fs;
+    use std::process::Command;
+
+    // A relative path would be resolved against `target` once the command runs in it.
+    let status = Command::new(fs::canonicalize(appimage)?)
+        .arg("--appimage-extract")
+        .current_dir(target)
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("Extracting {:?} failed with {}", appimage, status),
+        ))
+    }
+}
@@ -0,0 +1,66 @@
+//! The terminal progress bar, which does nothing without the `progress` feature.
+//! Progress is reported through `EventListener` either way.
+
+use crate::options::DownloadOptions;
+use std::time::Duration;
+
+#[cfg(feature = "progress")]
+pub(crate) use indicatif::ProgressBar;
+
+/// The progress bar for a download of `size` bytes, or a spinner if the size isn't known.
+/// Hidden if `options.quiet` is set.
+#[cfg(feature = "progress")]
+pub(crate) fn bar(size: Option<u64>, options: &DownloadOptions) -> ProgressBar {
+    use indicatif::ProgressStyle;
+
+    let pb = match size {
+        _ if options.quiet => ProgressBar::hidden(),
+        Some(size) => ProgressBar::new(size),
+        None => ProgressBar::new_spinner(),
+    };
+    if let Some(refresh_rate) = options.progress.refresh_rate.filter(|_| !options.quiet) {
+        use indicatif::ProgressDrawTarget;
+
+        pb.set_draw_target(ProgressDrawTarget::stderr_with_hz(refresh_rate));
+    }
+    let style = match size {
+        Some(_) => ProgressStyle::default_bar()
+            .template(&options.progress.template)
+            .progress_chars(&options.progress.progress_chars),
+        None => ProgressStyle::default_spinner().template(&options.progress.spinner_template),
+    };
+    pb.set_style(style);
+
+    pb
+}
+
+#[cfg(feature = "progress")]
+pub(crate) fn format_duration(duration: Duration) -> String {
+    indicatif::HumanDuration(duration).to_string()
+}
+
+#[cfg(not(feature = "progress"))]
+pub(crate) struct ProgressBar;
+
+#[cfg(not(feature = "progress"))]
+impl ProgressBar {
+    pub(crate) fn inc(&self, _delta: u64) {}
+
+    pub(crate) fn set_length(&self, _len: u64) {}
+
+    pub(crate) fn set_position(&self, _pos: u64) {}
+
+    pub(crate) fn set_message(&self, _message: &str) {}
+
+    pub(crate) fn finish(&self) {}
+}
+
+#[cfg(not(feature = "progress"))]
+pub(crate) fn bar(_size: Option<u64>, _options: &DownloadOptions) -> ProgressBar {
+    ProgressBar
+}
+
+#[cfg(not(feature = "progress"))]
+pub(crate) fn format_duration(duration: Duration) -> String {
+    format!("{} seconds", duration.as_secs())
+}
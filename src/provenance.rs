@@ -0,0 +1,275 @@
+#[cfg(feature = "blocking")]
+use crate::checksum::{self, DigestAlgorithm};
+use crate::keys::KeyStore;
+#[cfg(feature = "blocking")]
+use crate::{companion, keys::TrustedKey, metadata, options::DownloadOptions, DownloadError};
+#[cfg(feature = "blocking")]
+use just_core::result::BoxedResult;
+#[cfg(feature = "blocking")]
+use serde_json::Value;
+#[cfg(feature = "blocking")]
+use std::path::Path;
+
+/// What the SLSA provenance of an artifact has to state. The DSSE envelope of the statement
+/// must be signed by one of the publisher's trusted Ed25519 or ECDSA P-256 keys, stored as
+/// PEM encoded public keys.
+#[derive(Debug, Clone)]
+pub struct ProvenancePolicy {
+    /// Where to get the provenance (`.intoto.jsonl`), may contain `{version}`.
+    pub url: String,
+    pub key_store: KeyStore,
+    /// Whose keys in `key_store` are trusted.
+    pub publisher: String,
+    /// The builder that must have produced the artifact.
+    pub builder_id: Option<String>,
+    /// The repository the artifact must have been built from, e.g. `github.com/owner/repo`.
+    pub source_repo: Option<String>,
+}
+
+/// The verified claims of an artifact's signed provenance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub builder_id: String,
+    pub source_uri: Option<String>,
+}
+
+#[cfg(feature = "blocking")]
+impl Provenance {
+    fn from_statement(statement: &Value) -> Option<Self> {
+        let predicate = &statement["predicate"];
+        // SLSA v0.2 and v1 keep these in different places.
+        let builder_id = predicate["builder"]["id"]
+            .as_str()
+            .or_else(|| predicate["runDetails"]["builder"]["id"].as_str())?
+            .to_owned();
+        let source_uri = predicate["invocation"]["configSource"]["uri"]
+            .as_str()
+            .or_else(|| {
+                predicate["buildDefinition"]["externalParameters"]["workflow"]["repository"]
+                    .as_str()
+            })
+            .map(str::to_owned);
+
+        Some(Self {
+            builder_id,
+            source_uri,
+        })
+    }
+}
+
+/// Reduces `git+https://github.com/owner/repo.git@refs/tags/v1` to `github.com/owner/repo`.
+#[cfg(feature = "blocking")]
+fn normalize_repo(uri: &str) -> String {
+    let uri = uri.trim_start_matches("git+");
+    let uri = uri.splitn(2, "://").last().unwrap_or(uri);
+    let uri = uri.split('@').next().unwrap_or(uri);
+
+    uri.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_ascii_lowercase()
+}
+
+#[cfg(feature = "blocking")]
+impl ProvenancePolicy {
+    fn check(&self, provenance: &Provenance) -> Result<(), DownloadError> {
+        if let Some(builder_id) = &self.builder_id {
+            if &provenance.builder_id != builder_id {
+                return Err(DownloadError::Verification(format!(
+                    "Artifact was built by '{}' instead of '{}'",
+                    provenance.builder_id, builder_id
+                )));
+            }
+        }
+
+        if let Some(source_repo) = &self.source_repo {
+            let matches = provenance.source_uri.as_ref().map_or(false, |uri| {
+                normalize_repo(uri) == normalize_repo(source_repo)
+            });
+            if !matches {
+                return Err(DownloadError::Verification(format!(
+                    "Artifact was built from {:?} instead of '{}'",
+                    provenance.source_uri, source_repo
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The DER encoded SubjectPublicKeyInfo of a key up to the raw key itself.
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+const ED25519_SPKI: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+const P256_SPKI: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// Whether `signature` of `message` has been made with `key`, a PEM encoded Ed25519 or
+/// ECDSA P-256 public key.
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+fn is_signed_by(key: &TrustedKey, message: &[u8], signature: &[u8]) -> bool {
+    use ring::signature::{UnparsedPublicKey, VerificationAlgorithm};
+    use ring::signature::{ECDSA_P256_SHA256_ASN1, ED25519};
+
+    let pem = String::from_utf8_lossy(&key.key);
+    let der: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+    let der = match base64::decode(&der) {
+        Ok(der) => der,
+        Err(_) => return false,
+    };
+    let (algorithm, public_key): (&'static dyn VerificationAlgorithm, &[u8]) =
+        if der.starts_with(&ED25519_SPKI) {
+            (&ED25519, &der[ED25519_SPKI.len()..])
+        } else if der.starts_with(&P256_SPKI) {
+            (&ECDSA_P256_SHA256_ASN1, &der[P256_SPKI.len()..])
+        } else {
+            return false;
+        };
+
+    UnparsedPublicKey::new(algorithm, public_key)
+        .verify(message, signature)
+        .is_ok()
+}
+
+/// The pre-authentication encoding of DSSE, what the envelope signatures sign.
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+
+    encoded
+}
+
+/// Unwraps the in-toto statement of a DSSE envelope, if one of `keys` signed it.
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+fn signed_statement(line: &str, keys: &[TrustedKey]) -> BoxedResult<Option<Value>> {
+    let envelope: Value = serde_json::from_str(line)?;
+    let payload = match envelope["payload"].as_str() {
+        Some(payload) => base64::decode(payload)?,
+        None => return Ok(None),
+    };
+    let message = pae(envelope["payloadType"].as_str().unwrap_or(""), &payload);
+    let signed = envelope["signatures"]
+        .as_array()
+        .map_or(false, |signatures| {
+            signatures
+                .iter()
+                .filter_map(|signature| signature["sig"].as_str())
+                .filter_map(|signature| base64::decode(signature).ok())
+                .any(|signature| {
+                    keys.iter()
+                        .any(|key| is_signed_by(key, &message, &signature))
+                })
+        });
+
+    if signed {
+        Ok(Some(serde_json::from_slice(&payload)?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(all(feature = "blocking", not(feature = "signatures")))]
+fn signed_statement(_line: &str, _keys: &[TrustedKey]) -> BoxedResult<Option<Value>> {
+    Err("Verifying provenance requires the `signatures` feature".into())
+}
+
+#[cfg(feature = "blocking")]
+fn covers(statement: &Value, sha256: &str) -> bool {
+    statement["subject"].as_array().map_or(false, |subjects| {
+        subjects
+            .iter()
+            .any(|subject| subject["digest"]["sha256"].as_str() == Some(sha256))
+    })
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn verify(
+    policy: &ProvenancePolicy,
+    raw_version: &str,
+    artifact: &Path,
+    options: &DownloadOptions,
+) -> BoxedResult<Provenance> {
+    use log::info;
+
+    let url = companion::url(&policy.url, raw_version);
+    info!("Verifying provenance from {}", url);
+
+    let keys = policy.key_store.list(&policy.publisher)?;
+    if keys.is_empty() {
+        return Err(DownloadError::Verification(format!(
+            "No trusted keys of '{}' to verify the provenance with",
+            policy.publisher
+        ))
+        .into());
+    }
+
+    let document = metadata::get(&options.client(&url)?, &url, options)?
+        .ok_or_else(|| DownloadError::Policy(format!("No provenance published at {}", url)))?;
+    let document = String::from_utf8(document)?;
+    let sha256 = checksum::file_digest(DigestAlgorithm::Sha256, artifact)?;
+
+    for line in document.lines().filter(|line| !line.trim().is_empty()) {
+        let statement = match signed_statement(line, &keys)? {
+            Some(statement) if covers(&statement, &sha256) => statement,
+            _ => continue,
+        };
+
+        let provenance = Provenance::from_statement(&statement).ok_or_else(|| {
+            DownloadError::Verification(format!("Malformed provenance in {}", url))
+        })?;
+        policy.check(&provenance)?;
+
+        return Ok(provenance);
+    }
+
+    Err(DownloadError::Verification(format!(
+        "No provenance in {} signed by a trusted key of '{}' covers {:?}",
+        url, policy.publisher, artifact
+    ))
+    .into())
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_repository_uris() {
+        for uri in &[
+            "git+https://github.com/Owner/Repo.git@refs/tags/v1.2.3",
+            "https://github.com/owner/repo/",
+            "github.com/owner/repo",
+        ] {
+            assert_eq!(normalize_repo(uri), "github.com/owner/repo");
+        }
+        assert_ne!(
+            normalize_repo("https://github.com/owner/repo-fork"),
+            "github.com/owner/repo"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signatures")]
+    fn encodes_dsse_envelopes_for_signing() {
+        assert_eq!(
+            pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec()
+        );
+        assert_eq!(pae("", b""), b"DSSEv1 0  0 ".to_vec());
+    }
+}
@@ -0,0 +1,135 @@
+//! A cap on the retries of all downloads of a batch together.
+
+use crate::options::DownloadOptions;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Limits how often, and how long, the downloads sharing it are attempted again, so a
+/// perpetually failing server can't stretch a batch indefinitely.
+#[derive(Debug, Default)]
+pub struct RetryBudget {
+    max_retries: Option<usize>,
+    max_time: Option<Duration>,
+    spent: Mutex<Spent>,
+}
+
+#[derive(Debug, Default)]
+struct Spent {
+    retries: usize,
+    time: Duration,
+}
+
+impl RetryBudget {
+    /// Allows at most `retries` retries over all downloads.
+    pub fn retries(retries: usize) -> Self {
+        Self {
+            max_retries: Some(retries),
+            ..Self::default()
+        }
+    }
+
+    /// Allows retrying for at most `time` over all downloads, counting the attempts after
+    /// the first one.
+    pub fn time(time: Duration) -> Self {
+        Self {
+            max_time: Some(time),
+            ..Self::default()
+        }
+    }
+
+    /// Allows at most `time` spent retrying, in addition to the retry limit.
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.max_time = Some(time);
+        self
+    }
+
+    /// Why nothing may be retried anymore, if the budget is exhausted.
+    pub fn exhausted(&self) -> Option<String> {
+        self.exhausted_by(&self.spent())
+    }
+
+    fn exhausted_by(&self, spent: &Spent) -> Option<String> {
+        match (self.max_retries, self.max_time) {
+            (Some(max_retries), _) if spent.retries >= max_retries => Some(format!(
+                "the retry budget of {} retries is exhausted",
+                max_retries
+            )),
+            (_, Some(max_time)) if spent.time >= max_time => Some(format!(
+                "the retry budget of {} seconds is exhausted",
+                max_time.as_secs()
+            )),
+            _ => None,
+        }
+    }
+
+    /// Takes a retry out of the budget, or tells why there's none left.
+    pub(crate) fn take(&self) -> Result<(), String> {
+        // Under one lock, so concurrent downloads can't both take the last retry.
+        let mut spent = self.spent();
+        if let Some(reason) = self.exhausted_by(&spent) {
+            return Err(reason);
+        }
+        spent.retries += 1;
+
+        Ok(())
+    }
+
+    /// Counts `time` spent on a retry.
+    pub(crate) fn spend(&self, time: Duration) {
+        self.spent().time += time;
+    }
+
+    fn spent(&self) -> std::sync::MutexGuard<Spent> {
+        self.spent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl DownloadOptions {
+    /// Takes a retry out of the retry budget, if there is one.
+    pub(crate) fn take_retry(&self) -> Result<(), String> {
+        match &self.retry_budget {
+            Some(retry_budget) => retry_budget.take(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_retries_until_exhausted() {
+        let budget = RetryBudget::retries(2);
+        assert_eq!(budget.take(), Ok(()));
+        assert_eq!(budget.take(), Ok(()));
+        assert_eq!(
+            budget.take(),
+            Err("the retry budget of 2 retries is exhausted".to_owned())
+        );
+        assert!(budget.exhausted().is_some());
+    }
+
+    #[test]
+    fn counts_the_time_spent_retrying() {
+        let budget = RetryBudget::retries(10).with_time(Duration::from_secs(60));
+        budget.spend(Duration::from_secs(30));
+        assert_eq!(budget.take(), Ok(()));
+        budget.spend(Duration::from_secs(30));
+        assert_eq!(
+            budget.take(),
+            Err("the retry budget of 60 seconds is exhausted".to_owned())
+        );
+    }
+
+    #[test]
+    fn allows_every_retry_without_limits() {
+        let budget = RetryBudget::default();
+        for _ in 0..100 {
+            assert_eq!(budget.take(), Ok(()));
+        }
+        assert_eq!(budget.exhausted(), None);
+    }
+}
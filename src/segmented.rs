@@ -0,0 +1,365 @@
+//! Downloading an artifact in several byte ranges at once.
+
+use crate::error::DownloadError;
+use crate::options::DownloadOptions;
+use just_core::result::BoxedResult;
+use reqwest::Client;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// The size of the chunks a swarm download is split into.
+const SWARM_CHUNK: u64 = 4 * 1024 * 1024;
+
+/// The ranges `size` bytes are split into, at least one byte each.
+pub(crate) fn ranges(size: u64, segments: usize) -> Vec<Range<u64>> {
+    let segments = (segments.max(1) as u64).min(size.max(1));
+    let length = size.div_ceil(segments);
+
+    (0..segments)
+        .map(|i| (i * length)..((i + 1) * length).min(size))
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// The artifact the ranges are requested of.
+pub(crate) struct Remote<'a> {
+    pub(crate) url: &'a str,
+    /// The strong ETag or the Last-Modified date of the artifact, sent as `If-Range` so
+    /// ranges of an artifact changed in between aren't stitched together.
+    pub(crate) validator: Option<&'a str>,
+    pub(crate) options: &'a DownloadOptions,
+}
+
+/// Downloads the `size` bytes of `remote` into `path` with `segments` concurrent range requests.
+/// `path` is created as a sparse file, so ranges not yet received don't take up space, and
+/// written through a memory map if `mmap` is set. `progress` is told about every chunk written.
+pub(crate) fn download(
+    client: &Client,
+    remote: &Remote,
+    path: &Path,
+    size: u64,
+    segments: usize,
+    mmap: bool,
+    progress: &(dyn Fn(u64) + Sync),
+) -> BoxedResult<u64> {
+    let file = create_sparse(path, size)?;
+    let ranges = ranges(size, segments);
+    #[cfg(feature = "mmap")]
+    {
+        if mmap {
+            return download_mapped(client, remote, &file, &ranges, progress);
+        }
+    }
+    #[cfg(not(feature = "mmap"))]
+    let _ = mmap;
+
+    let results = in_parallel(&ranges, |range| {
+        let file = &file;
+        fetch_range(
+            client,
+            remote,
+            range.clone(),
+            progress,
+            &mut |offset, buf| write_at(file, buf, offset),
+        )
+    });
+
+    results.into_iter().sum()
+}
+
+/// Downloads the `size` bytes into `path` in chunks of `SWARM_CHUNK`, taken by a thread per
+/// remote as long as there are any left, so faster servers end up transferring more of the
+/// artifact. The chunk of a failing remote is left to the others.
+pub(crate) fn swarm(
+    remotes: &[(Client, Remote)],
+    path: &Path,
+    size: u64,
+    progress: &(dyn Fn(u64) + Sync),
+) -> BoxedResult<u64> {
+    use log::warn;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    struct Chunks {
+        pending: Vec<Range<u64>>,
+        in_flight: usize,
+    }
+
+    let file = create_sparse(path, size)?;
+    let count = size.div_ceil(SWARM_CHUNK) as usize;
+    let mut pending = ranges(size, count.max(remotes.len()));
+    pending.reverse();
+    let chunks = Mutex::new(Chunks {
+        pending,
+        in_flight: 0,
+    });
+    let failures = Mutex::new(Vec::new());
+    let changed = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for (client, remote) in remotes {
+            let (file, chunks, failures, changed) = (&file, &chunks, &failures, &changed);
+            scope.spawn(move || loop {
+                let range = {
+                    let mut chunks = chunks.lock().unwrap();
+                    let next = chunks.pending.pop();
+                    match next {
+                        Some(range) => {
+                            chunks.in_flight += 1;
+                            range
+                        }
+                        None if chunks.in_flight == 0 => break,
+                        // A chunk of a failing remote may still come back.
+                        None => {
+                            drop(chunks);
+                            thread::sleep(Duration::from_millis(50));
+                            continue;
+                        }
+                    }
+                };
+
+                let result =
+                    fetch_range(client, remote, range.clone(), progress, &mut |at, buf| {
+                        write_at(file, buf, at)
+                    });
+                let mut chunks = chunks.lock().unwrap();
+                chunks.in_flight -= 1;
+                if let Err(e) = result {
+                    warn!("Dropping {} from the download: {}", remote.url, e);
+                    if let Some(DownloadError::Changed(url)) = e.downcast_ref::<DownloadError>() {
+                        *changed.lock().unwrap() = Some(url.clone());
+                    }
+                    failures
+                        .lock()
+                        .unwrap()
+                        .push(format!("{}: {}", remote.url, e));
+                    chunks.pending.push(range);
+                    break;
+                }
+            });
+        }
+    });
+
+    if let Some(url) = changed.into_inner().unwrap() {
+        return Err(DownloadError::Changed(url).into());
+    }
+    if !chunks.into_inner().unwrap().pending.is_empty() {
+        return Err(format!(
+            "Every server failed: {}",
+            failures.into_inner().unwrap().join(", ")
+        )
+        .into());
+    }
+
+    Ok(size)
+}
+
+/// Creates `path` as a sparse file of `size` bytes.
+fn create_sparse(path: &Path, size: u64) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    crate::platform::make_sparse(&file)?;
+    file.set_len(size)?;
+
+    Ok(file)
+}
+
+/// Writes the ranges into a memory map of `file`, saving a syscall per chunk.
+#[cfg(feature = "mmap")]
+fn download_mapped(
+    client: &Client,
+    remote: &Remote,
+    file: &File,
+    ranges: &[Range<u64>],
+    progress: &(dyn Fn(u64) + Sync),
+) -> BoxedResult<u64> {
+    use memmap2::MmapMut;
+    use std::mem;
+    use std::sync::Mutex;
+
+    let mut map = unsafe { MmapMut::map_mut(file)? };
+    let mut rest = &mut map[..];
+    let mut slices = Vec::new();
+    for range in ranges {
+        let (slice, tail) = mem::take(&mut rest).split_at_mut((range.end - range.start) as usize);
+        slices.push(Mutex::new(slice));
+        rest = tail;
+    }
+
+    let segments: Vec<(Range<u64>, Mutex<&mut [u8]>)> =
+        ranges.iter().cloned().zip(slices).collect();
+    let results = in_parallel(&segments, |(range, slice)| {
+        let mut slice = slice.lock().unwrap();
+        let start = range.start;
+        fetch_range(
+            client,
+            remote,
+            range.clone(),
+            progress,
+            &mut |offset, buf| {
+                let at = (offset - start) as usize;
+                slice[at..at + buf.len()].copy_from_slice(buf);
+                Ok(())
+            },
+        )
+    });
+    let transferred = results.into_iter().sum::<BoxedResult<u64>>()?;
+    drop(segments);
+    map.flush()?;
+
+    Ok(transferred)
+}
+
+/// Runs `f` for every item on a thread of its own.
+fn in_parallel<T: Sync, F>(items: &[T], f: F) -> Vec<BoxedResult<u64>>
+where
+    F: Fn(&T) -> BoxedResult<u64> + Sync,
+{
+    use std::thread;
+
+    let results: Vec<Result<u64, SendError>> = thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .iter()
+            .map(|item| {
+                let f = &f;
+                scope.spawn(move || f(item).map_err(sendable))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err("Segment download panicked".into()))
+            })
+            .collect()
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.map_err(|e| -> Box<dyn Error> { e }))
+        .collect()
+}
+
+type SendError = Box<dyn Error + Send + Sync>;
+
+/// `e` as an error that can cross threads. `Box<dyn Error>` isn't `Send`, so the errors a
+/// segment fails with are moved as their own type, which decides whether the download is
+/// retried. Any other error crosses as its message.
+fn sendable(e: Box<dyn Error>) -> SendError {
+    let e = match e.downcast::<DownloadError>() {
+        Ok(e) => return e,
+        Err(e) => e,
+    };
+    let e = match e.downcast::<reqwest::Error>() {
+        Ok(e) => return e,
+        Err(e) => e,
+    };
+    match e.downcast::<io::Error>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
+
+fn fetch_range(
+    client: &Client,
+    remote: &Remote,
+    range: Range<u64>,
+    progress: &(dyn Fn(u64) + Sync),
+    write: &mut dyn FnMut(u64, &[u8]) -> io::Result<()>,
+) -> BoxedResult<u64> {
+    use reqwest::header::{IF_RANGE, RANGE};
+    use reqwest::StatusCode;
+    use std::io::Read;
+
+    let url = remote.url;
+    let mut request = client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", range.start, range.end - 1));
+    if let Some(validator) = remote.validator {
+        request = request.header(IF_RANGE, validator);
+    }
+    let mut response = remote.options.send(client, request)?.error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        // With `If-Range`, the whole artifact is sent instead if it has changed.
+        return Err(match remote.validator {
+            Some(_) => DownloadError::Changed(url.to_owned()).into(),
+            None => format!("{} ignored the range request", url).into(),
+        });
+    }
+
+    let mut offset = range.start;
+    let mut buf = [0u8; 64 * 1024];
+    while offset < range.end {
+        let n = response.read(&mut buf)?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Range {}-{} of {} ended early", range.start, range.end, url),
+            )
+            .into());
+        }
+        let n = n.min((range.end - offset) as usize);
+        write(offset, &buf[..n])?;
+        offset += n as u64;
+        progress(n as u64);
+    }
+
+    Ok(range.end - range.start)
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, offset)?;
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_into_equal_ranges() {
+        assert_eq!(ranges(10, 3), vec![0..4, 4..8, 8..10]);
+        assert_eq!(ranges(9, 3), vec![0..3, 3..6, 6..9]);
+    }
+
+    #[test]
+    fn keeps_at_least_one_byte_per_range() {
+        assert_eq!(ranges(2, 8), vec![0..1, 1..2]);
+        assert_eq!(ranges(10, 0), vec![0..10]);
+        assert_eq!(ranges(0, 4), Vec::<Range<u64>>::new());
+    }
+
+    #[test]
+    fn covers_every_byte_once() {
+        let size = 1_000_003;
+        let ranges = ranges(size, 7);
+        assert_eq!(ranges.first().map(|range| range.start), Some(0));
+        assert_eq!(ranges.last().map(|range| range.end), Some(size));
+        assert!(ranges.windows(2).all(|pair| pair[0].end == pair[1].start));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::keys::KeyStore;
+#[cfg(feature = "blocking")]
+use crate::{keys::TrustedKey, metadata, options::DownloadOptions, DownloadError};
+#[cfg(feature = "blocking")]
+use just_core::result::BoxedResult;
+#[cfg(feature = "blocking")]
+use reqwest::Client;
+#[cfg(feature = "blocking")]
+use std::path::Path;
+
+#[cfg(feature = "blocking")]
+const SUFFIXES: [&str; 3] = [".minisig", ".asc", ".sig"];
+
+/// Verifies the artifact against a companion signature (`.minisig`, `.asc` or `.sig`)
+/// made by one of the publisher's trusted keys.
+#[derive(Debug, Clone)]
+pub struct SignaturePolicy {
+    pub key_store: KeyStore,
+    /// Whose keys in `key_store` are trusted.
+    pub publisher: String,
+    /// Fails instead of warning when no signature is published.
+    pub required: bool,
+}
+
+#[cfg(feature = "blocking")]
+fn signature_url(artifact_url: &str, suffix: &str) -> BoxedResult<String> {
+    use reqwest::Url;
+
+    let mut url = Url::parse(artifact_url)?;
+    url.set_fragment(None);
+    let path = format!("{}{}", url.path(), suffix);
+    url.set_path(&path);
+
+    Ok(url.into_string())
+}
+
+#[cfg(feature = "blocking")]
+fn is_minisign(signature: &[u8]) -> bool {
+    signature.starts_with(b"untrusted comment:")
+}
+
+#[cfg(all(feature = "blocking", feature = "signatures"))]
+fn verify_minisign(key: &TrustedKey, signature: &[u8], data: &[u8]) -> BoxedResult<()> {
+    use minisign_verify::{PublicKey, Signature};
+    use std::str;
+
+    let key = str::from_utf8(&key.key)?;
+    let public_key = PublicKey::decode(key).or_else(|_| PublicKey::from_base64(key.trim()))?;
+    let signature = Signature::decode(str::from_utf8(signature)?)?;
+    public_key.verify(data, &signature, false)?;
+
+    Ok(())
+}
+
+#[cfg(all(feature = "blocking", not(feature = "signatures")))]
+fn verify_minisign(_key: &TrustedKey, _signature: &[u8], _data: &[u8]) -> BoxedResult<()> {
+    Err("Verifying minisign signatures requires the `signatures` feature".into())
+}
+
+#[cfg(all(feature = "blocking", feature = "openpgp"))]
+fn verify_openpgp(key: &TrustedKey, signature: &[u8], data: &[u8]) -> BoxedResult<()> {
+    use pgp::{Deserializable, SignedPublicKey, StandaloneSignature};
+    use std::str;
+
+    let (public_key, _) = SignedPublicKey::from_string(str::from_utf8(&key.key)?)?;
+    let signature = if signature.starts_with(b"-----BEGIN") {
+        StandaloneSignature::from_string(str::from_utf8(signature)?)?.0
+    } else {
+        StandaloneSignature::from_bytes(signature)?
+    };
+    signature.verify(&public_key, data)?;
+
+    Ok(())
+}
+
+#[cfg(all(feature = "blocking", not(feature = "openpgp")))]
+fn verify_openpgp(_key: &TrustedKey, _signature: &[u8], _data: &[u8]) -> BoxedResult<()> {
+    Err("Verifying OpenPGP signatures requires the `openpgp` feature".into())
+}
+
+/// Looks for a signature next to `artifact_url` and verifies `artifact` with it.
+/// Returns whether a signature has been found.
+#[cfg(feature = "blocking")]
+pub(crate) fn verify(
+    client: &Client,
+    policy: &SignaturePolicy,
+    artifact_url: &str,
+    artifact: &Path,
+    options: &DownloadOptions,
+) -> BoxedResult<bool> {
+    use log::{info, warn};
+    use std::fs;
+
+    let keys = policy.key_store.list(&policy.publisher)?;
+
+    for suffix in SUFFIXES.iter() {
+        let url = signature_url(artifact_url, suffix)?;
+        let signature = match metadata::get(client, &url, options)? {
+            Some(signature) => signature,
+            None => continue,
+        };
+        info!("Verifying {:?} with {}", artifact, url);
+
+        let data = fs::read(artifact)?;
+        let verified = keys.iter().any(|key| {
+            let result = if is_minisign(&signature) {
+                verify_minisign(key, &signature, &data)
+            } else {
+                verify_openpgp(key, &signature, &data)
+            };
+
+            result.is_ok()
+        });
+
+        return if verified {
+            Ok(true)
+        } else {
+            Err(DownloadError::Verification(format!(
+                "{} isn't signed by a trusted key of '{}'",
+                url, policy.publisher
+            ))
+            .into())
+        };
+    }
+
+    if policy.required {
+        Err(DownloadError::Policy(format!("No signature published for {}", artifact_url)).into())
+    } else {
+        warn!("No signature published for {}", artifact_url);
+        Ok(false)
+    }
+}
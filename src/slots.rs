@@ -0,0 +1,139 @@
+//! Transfer slots shared by concurrent batches, taken over by downloads of a higher priority.
+
+use crate::options::DownloadOptions;
+use std::sync::{Condvar, Mutex};
+
+/// How urgently a package of a batch is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Fetched ahead of time, e.g. for packages likely to be installed next.
+    Prefetch,
+    Normal,
+    /// Asked for by the user, who is waiting for it.
+    High,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// Lets at most `slots` downloads transfer at once, over every batch sharing it. The others
+/// wait, the ones of a lower priority first: a download of a higher priority starting while
+/// all slots are taken pauses the lowest one running at its next chunk, until a slot
+/// becomes free again. Downloads of the same priority take turns in the order they started.
+///
+/// A paused download keeps its connection, which the server may close meanwhile; the
+/// download is then retried.
+#[derive(Debug)]
+pub struct TransferSlots {
+    slots: usize,
+    holders: Mutex<Holders>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Holders {
+    entered: u64,
+    /// The package, priority and start of every download wanting a slot.
+    waiting: Vec<(String, Priority, u64)>,
+}
+
+impl Holders {
+    /// Whether `package` is among the `slots` downloads ranking highest, or not taking part.
+    fn has_slot(&self, package: &str, slots: usize) -> bool {
+        let (priority, started) = match self.waiting.iter().find(|(name, _, _)| name == package) {
+            Some((_, priority, started)) => (*priority, *started),
+            None => return true,
+        };
+        let outranked_by = self
+            .waiting
+            .iter()
+            .filter(|(_, other, other_started)| {
+                *other > priority || (*other == priority && *other_started < started)
+            })
+            .count();
+
+        outranked_by < slots
+    }
+}
+
+/// A download's claim on a slot, given up when dropped.
+pub(crate) struct Slot<'a> {
+    slots: &'a TransferSlots,
+    package: String,
+}
+
+impl TransferSlots {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            slots: slots.max(1),
+            holders: Mutex::new(Holders::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Waits for a slot for `package`, pausing the downloads it outranks.
+    pub(crate) fn enter(&self, package: &str, priority: Priority) -> Slot {
+        {
+            let mut holders = self.lock();
+            holders.entered += 1;
+            let started = holders.entered;
+            holders
+                .waiting
+                .push((package.to_owned(), priority, started));
+        }
+        self.wait(package);
+
+        Slot {
+            slots: self,
+            package: package.to_owned(),
+        }
+    }
+
+    /// Waits while `package` is outranked by the downloads holding every slot.
+    pub(crate) fn wait(&self, package: &str) {
+        let mut holders = self.lock();
+        while !holders.has_slot(package, self.slots) {
+            holders = self
+                .changed
+                .wait(holders)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<Holders> {
+        self.holders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut holders = self.slots.lock();
+        if let Some(index) = holders
+            .waiting
+            .iter()
+            .position(|(name, _, _)| *name == self.package)
+        {
+            holders.waiting.remove(index);
+        }
+        self.slots.changed.notify_all();
+    }
+}
+
+impl DownloadOptions {
+    /// Waits while `package` is paused for downloads of a higher priority, if the transfer
+    /// slots are shared.
+    pub(crate) fn wait_for_slot(&self, package: &str) {
+        if let Some(transfer_slots) = &self.transfer_slots {
+            transfer_slots.wait(package);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::options::{RateUnit, UnitPrefix};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Transfer speed averaged over a sliding window, which doesn't jump around on bursty links
+/// like the speed of the last chunk does.
+pub(crate) struct Throughput {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+    last_report: Option<Instant>,
+}
+
+impl Throughput {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+            last_report: None,
+        }
+    }
+
+    pub(crate) fn record(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.samples.push_back((now, bytes));
+        while self.samples.len() > 1
+            && self
+                .samples
+                .front()
+                .map(|(at, _)| now.duration_since(*at) > self.window)
+                .unwrap_or(false)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn bytes_per_second(&self) -> f64 {
+        let (first, _) = match self.samples.front() {
+            Some(sample) => sample,
+            None => return 0.0,
+        };
+        let elapsed = first.elapsed().as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        self.samples.iter().map(|(_, bytes)| *bytes).sum::<u64>() as f64 / elapsed
+    }
+
+    /// The time left for `remaining` bytes at the current speed.
+    pub(crate) fn eta(&self, remaining: u64) -> Option<Duration> {
+        let speed = self.bytes_per_second();
+        if speed > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / speed))
+        } else {
+            None
+        }
+    }
+
+    /// Whether a second has passed since this last returned `true`, to throttle reporting.
+    pub(crate) fn should_report(&mut self) -> bool {
+        let now = Instant::now();
+        match self.last_report {
+            Some(last) if now.duration_since(last) < Duration::from_secs(1) => false,
+            _ => {
+                self.last_report = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Formats a speed like `12.5 MiB/s` or `104.9 Mbit/s`.
+pub(crate) fn format_rate(bytes_per_second: f64, unit: RateUnit, prefix: UnitPrefix) -> String {
+    match unit {
+        RateUnit::Bytes => format!("{}B/s", scaled(bytes_per_second, prefix)),
+        RateUnit::Bits => format!("{}bit/s", scaled(bytes_per_second * 8.0, prefix)),
+    }
+}
+
+/// Formats a size like `12.5 MiB`.
+pub(crate) fn format_size(bytes: u64, prefix: UnitPrefix) -> String {
+    format!("{}B", scaled(bytes as f64, prefix))
+}
+
+/// `value` with the largest prefix keeping it above 1, like `12.5 Mi`.
+fn scaled(value: f64, prefix: UnitPrefix) -> String {
+    let (base, prefixes): (f64, [&str; 5]) = match prefix {
+        UnitPrefix::Binary => (1024.0, ["", "Ki", "Mi", "Gi", "Ti"]),
+        UnitPrefix::Decimal => (1000.0, ["", "k", "M", "G", "T"]),
+    };
+
+    let mut value = value;
+    let mut index = 0;
+    while value >= base && index < prefixes.len() - 1 {
+        value /= base;
+        index += 1;
+    }
+
+    format!("{:.1} {}", value, prefixes[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_byte_rates() {
+        assert_eq!(
+            format_rate(13_107_200.0, RateUnit::Bytes, UnitPrefix::Binary),
+            "12.5 MiB/s"
+        );
+        assert_eq!(
+            format_rate(512.0, RateUnit::Bytes, UnitPrefix::Binary),
+            "512.0 B/s"
+        );
+    }
+
+    #[test]
+    fn formats_bit_rates() {
+        assert_eq!(
+            format_rate(13_107_200.0, RateUnit::Bits, UnitPrefix::Decimal),
+            "104.9 Mbit/s"
+        );
+        assert_eq!(
+            format_rate(1_000.0, RateUnit::Bits, UnitPrefix::Binary),
+            "7.8 Kibit/s"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_largest_prefix() {
+        assert_eq!(
+            format_size(3 * 1024 * 1024 * 1024 * 1024 * 1024, UnitPrefix::Binary),
+            "3072.0 TiB"
+        );
+        assert_eq!(format_size(1_500_000, UnitPrefix::Decimal), "1.5 MB");
+    }
+}
@@ -0,0 +1,114 @@
+//! Opt-in statistics about downloads, e.g. collected over a fleet of machines installing
+//! the same tools. Nothing is reported without a `TelemetrySink`.
+
+use crate::error::ErrorCategory;
+use crate::options::DownloadOptions;
+use std::fmt;
+#[cfg(feature = "telemetry")]
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The key hosts are hashed with, random for every installation.
+#[cfg(feature = "telemetry")]
+static SALT: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Receives the outcome of every download. Hosts are pseudonymized as a hash of their name
+/// keyed with a random salt of the installation, which tells them apart but can't be
+/// reversed by hashing well-known hosts. Without the `telemetry` feature there's nothing to
+/// hash with, and hosts are reported as empty strings. Every method does nothing by default.
+pub trait TelemetrySink: Send + Sync {
+    fn started(&self, _host: &str) {}
+
+    /// `size` is the number of bytes transferred, 0 for an artifact that was up to date.
+    fn succeeded(&self, _host: &str, _size: u64, _duration: Duration) {}
+
+    fn failed(&self, _host: &str, _category: ErrorCategory, _duration: Duration) {}
+}
+
+impl fmt::Debug for dyn TelemetrySink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("TelemetrySink")
+    }
+}
+
+/// The salt stored in the cache directory, created on first use. Without a cache directory
+/// to store it in, the salt only lasts as long as the process. `None` if the system has no
+/// randomness to make one.
+#[cfg(feature = "telemetry")]
+fn salt() -> Option<Vec<u8>> {
+    use crate::checksum::to_hex;
+    use log::debug;
+    use ring::rand::{SecureRandom, SystemRandom};
+    use std::fs;
+
+    let mut known = SALT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if known.is_some() {
+        return known.clone();
+    }
+
+    let path = dirs::cache_dir().map(|cache| cache.join("just").join("telemetry-salt"));
+    let stored = path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|hex| hex.trim().to_owned())
+        .filter(|hex| hex.len() == 64);
+    let created = match stored {
+        Some(hex) => hex.into_bytes(),
+        None => {
+            let mut random = [0u8; 32];
+            SystemRandom::new().fill(&mut random).ok()?;
+            let hex = to_hex(&random);
+            if let Some(path) = &path {
+                let written = path
+                    .parent()
+                    .map(fs::create_dir_all)
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| fs::write(path, &hex));
+                if let Err(e) = written {
+                    debug!("Could not store the telemetry salt in {:?}: {}", path, e);
+                }
+            }
+            hex.into_bytes()
+        }
+    };
+
+    *known = Some(created);
+    known.clone()
+}
+
+/// The first 16 hex digits of the HMAC-SHA-256 of the host of `url`, keyed with the salt.
+/// Empty without a salt, as hosts are better not reported at all than reversibly.
+#[cfg(feature = "telemetry")]
+fn pseudonymized_host(url: &str) -> String {
+    use crate::checksum::to_hex;
+    use reqwest::Url;
+    use ring::hmac;
+
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default();
+    let salt = match salt() {
+        Some(salt) => salt,
+        None => return String::new(),
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &salt);
+    let mut hash = to_hex(hmac::sign(&key, host.as_bytes()).as_ref());
+    hash.truncate(16);
+
+    hash
+}
+
+#[cfg(not(feature = "telemetry"))]
+fn pseudonymized_host(_url: &str) -> String {
+    String::new()
+}
+
+impl DownloadOptions {
+    /// Calls `f` with the telemetry sink and the pseudonymized host of `url`, if there is one.
+    pub(crate) fn report<F: FnOnce(&dyn TelemetrySink, &str)>(&self, url: &str, f: F) {
+        if let Some(telemetry) = &self.telemetry {
+            f(telemetry.as_ref(), &pseudonymized_host(url));
+        }
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(feature = "blocking")]
+use crate::checksum::{to_hex, Checksum, DigestAlgorithm};
+#[cfg(feature = "blocking")]
+use just_core::result::BoxedResult;
+use std::path::PathBuf;
+
+/// Where to find the TUF repository protecting the artifacts.
+#[derive(Debug, Clone, Default)]
+pub struct TufConfig {
+    /// The trusted `root.json` shipped out of band.
+    pub root: PathBuf,
+    pub metadata_url: String,
+    pub targets_url: String,
+    /// Keeps the latest metadata between runs, which is what protects against rollbacks.
+    pub datastore: Option<PathBuf>,
+}
+
+/// Looks up the digest of `target_name` from signed, fresh TUF metadata.
+#[cfg(feature = "blocking")]
+pub(crate) fn trusted_checksum(config: &TufConfig, target_name: &str) -> BoxedResult<Checksum> {
+    use log::info;
+    use std::fs::File;
+    use tough::{RepositoryLoader, TargetName};
+    use url::Url;
+
+    info!("Loading TUF metadata from {}", config.metadata_url);
+    let mut loader = RepositoryLoader::new(
+        File::open(&config.root)?,
+        Url::parse(&config.metadata_url)?,
+        Url::parse(&config.targets_url)?,
+    );
+    if let Some(datastore) = &config.datastore {
+        loader = loader.datastore(datastore);
+    }
+    let repository = loader.load()?;
+
+    let target = repository
+        .targets()
+        .signed
+        .targets
+        .get(&TargetName::new(target_name)?)
+        .ok_or_else(|| format!("'{}' isn't a target of the TUF repository", target_name))?;
+
+    Ok(Checksum {
+        algorithm: DigestAlgorithm::Sha256,
+        digest: to_hex(&target.hashes.sha256),
+    })
+}
@@ -0,0 +1,375 @@
+//! Picking the version to download from the versions a manifest lists.
+
+use crate::error::DownloadError;
+use just_core::manifest::Manifest;
+use semver::{ReqParseError, Version, VersionReq};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether pre-releases like `1.2.0-rc.1` are considered when resolving a version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prereleases {
+    /// Only releases, and pre-releases the requirement names explicitly.
+    Exclude,
+    /// Pre-releases of versions the requirement allows, e.g. `1.2.0-rc.1` for `^1.1`.
+    Include,
+    /// The latest pre-release the requirement allows, even if there's a newer release.
+    Latest,
+}
+
+impl Default for Prereleases {
+    fn default() -> Self {
+        Prereleases::Exclude
+    }
+}
+
+/// How build metadata like the `+build.7` of `1.2.3+build.7` is put into URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildMetadata {
+    /// Percent-encoded as `1.2.3%2Bbuild.7`, since many servers read a `+` as a space.
+    Encoded,
+    /// As it is, `1.2.3+build.7`.
+    Literal,
+    /// Left out, `1.2.3`.
+    Strip,
+}
+
+impl Default for BuildMetadata {
+    fn default() -> Self {
+        BuildMetadata::Encoded
+    }
+}
+
+/// Which version to download, a requirement or one of the `latest`, `stable` or `lts` channels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionSelector {
+    /// The highest version matching the requirement.
+    Matching(VersionReq),
+    /// The highest version, pre-releases included.
+    Latest,
+    /// The highest release, what no requirement at all picks.
+    Stable,
+    /// The highest release matching `DownloadOptions::lts`.
+    Lts,
+}
+
+impl VersionSelector {
+    /// Whether exactly `version` is asked for, like `=1.2.3`.
+    pub fn pins(&self, version: &Version) -> bool {
+        match self {
+            VersionSelector::Matching(req) => *req == VersionReq::exact(version),
+            _ => false,
+        }
+    }
+}
+
+impl From<VersionReq> for VersionSelector {
+    fn from(req: VersionReq) -> Self {
+        VersionSelector::Matching(req)
+    }
+}
+
+impl From<Option<VersionReq>> for VersionSelector {
+    fn from(req: Option<VersionReq>) -> Self {
+        req.map(VersionSelector::Matching)
+            .unwrap_or(VersionSelector::Stable)
+    }
+}
+
+impl fmt::Display for VersionSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionSelector::Matching(req) => write!(f, "{}", req),
+            VersionSelector::Latest => f.write_str("latest"),
+            VersionSelector::Stable => f.write_str("stable"),
+            VersionSelector::Lts => f.write_str("lts"),
+        }
+    }
+}
+
+impl FromStr for VersionSelector {
+    type Err = ReqParseError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        match selector.trim().to_ascii_lowercase().as_str() {
+            "latest" => Ok(VersionSelector::Latest),
+            "stable" => Ok(VersionSelector::Stable),
+            "lts" => Ok(VersionSelector::Lts),
+            _ => VersionReq::parse(selector).map(VersionSelector::Matching),
+        }
+    }
+}
+
+/// Orders versions which aren't semver, like `2024.05` or `r27`.
+pub trait VersionScheme: Send + Sync {
+    fn compare(&self, a: &str, b: &str) -> Ordering;
+}
+
+impl<F: Fn(&str, &str) -> Ordering + Send + Sync> VersionScheme for F {
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        self(a, b)
+    }
+}
+
+impl fmt::Debug for dyn VersionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("VersionScheme")
+    }
+}
+
+/// Compares runs of digits numerically and everything else character by character,
+/// so `2024.05` < `2024.10` and `r9` < `r27`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Segmented;
+
+impl VersionScheme for Segmented {
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        segments(a).cmp(&segments(b))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    Text(String),
+    Number(u64),
+}
+
+fn segments(version: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut chars = version.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let is_digit = c.is_ascii_digit();
+        let mut segment = String::new();
+        while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() == is_digit) {
+            segment.push(c);
+            chars.next();
+        }
+        segments.push(match segment.parse() {
+            Ok(number) if is_digit => Segment::Number(number),
+            _ => Segment::Text(segment),
+        });
+    }
+
+    segments
+}
+
+/// Reads the first three numbers of a version which isn't semver as major, minor and
+/// patch, e.g. `2024.05` as `2024.5.0` and `r27` as `27.0.0`.
+pub fn coerce(version: &str) -> Option<Version> {
+    let mut numbers = segments(version)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Number(number) => Some(number),
+            Segment::Text(_) => None,
+        });
+    let major = numbers.next()?;
+
+    Some(Version::new(
+        major,
+        numbers.next().unwrap_or(0),
+        numbers.next().unwrap_or(0),
+    ))
+}
+
+/// The versions listed by `manifest`, as they are written.
+pub(crate) fn listed_raw(manifest: &Manifest) -> Vec<String> {
+    manifest
+        .versions
+        .iter()
+        .flatten()
+        .map(|version| version.as_ref().to_owned())
+        .collect()
+}
+
+/// The version without the `v` prefix of tags like `v1.2.3`.
+pub fn strip_prefix(version: &str) -> &str {
+    let trimmed = version.trim();
+    match trimmed.strip_prefix(|c| c == 'v' || c == 'V') {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest,
+        _ => trimmed,
+    }
+}
+
+/// Parses `1.2.3` as well as `v1.2.3`.
+pub fn parse(version: &str) -> Option<Version> {
+    Version::parse(strip_prefix(version)).ok()
+}
+
+/// `version` as it's put into URLs.
+pub fn url_version(version: &Version, build_metadata: BuildMetadata) -> String {
+    let mut release = version.clone();
+    release.build.clear();
+    if version.build.is_empty() || build_metadata == BuildMetadata::Strip {
+        return release.to_string();
+    }
+
+    let build = version
+        .build
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(".");
+    match build_metadata {
+        BuildMetadata::Literal => format!("{}+{}", release, build),
+        _ => format!("{}%2B{}", release, build),
+    }
+}
+
+/// Fills a URL template with `version`: `{version}` without a `v` prefix, so templates
+/// write `v{version}` if they need one, and `{raw_version}` as it's listed.
+pub fn substitute(template: &str, version: &str) -> String {
+    template
+        .replace("{raw_version}", version)
+        .replace("{version}", strip_prefix(version))
+}
+
+/// Fails if `url`, filled by `substitute`, has a placeholder left.
+pub(crate) fn check_substituted(url: &str) -> Result<(), DownloadError> {
+    match placeholder(url) {
+        Some(placeholder) => Err(DownloadError::InvalidUrl(format!(
+            "{} has {} left after substituting the version",
+            url, placeholder
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The first `{placeholder}` left in `url`, braces included.
+pub(crate) fn placeholder(url: &str) -> Option<&str> {
+    url.match_indices('{').find_map(|(start, _)| {
+        let end = start + url[start..].find('}')?;
+        let name = &url[start + 1..end];
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+        if is_name {
+            Some(&url[start..=end])
+        } else {
+            None
+        }
+    })
+}
+
+/// The versions of `raw` which can be parsed.
+pub(crate) fn parsed(raw: &[String]) -> Vec<Version> {
+    raw.iter().filter_map(|version| parse(version)).collect()
+}
+
+/// The highest of the `raw` versions which aren't semver, ordered by `scheme` or,
+/// without one, the last listed. `req` is matched against the coerced version.
+pub(crate) fn find_unversioned(
+    raw: &[String],
+    req: Option<&VersionReq>,
+    scheme: Option<&dyn VersionScheme>,
+) -> Option<(String, Version)> {
+    let candidates = raw
+        .iter()
+        .filter(|raw| parse(raw).is_none())
+        .filter_map(|raw| coerce(raw).map(|version| (raw.clone(), version)))
+        .filter(|(_, version)| req.map(|req| req.matches(version)).unwrap_or(true));
+
+    match scheme {
+        Some(scheme) => candidates.max_by(|(a, _), (b, _)| scheme.compare(a, b)),
+        None => candidates.last(),
+    }
+}
+
+/// Whether `version` satisfies `req`, treating a pre-release like its release if
+/// pre-releases are wanted.
+pub fn matches(version: &Version, req: Option<&VersionReq>, prereleases: Prereleases) -> bool {
+    let req = match req {
+        Some(req) => req,
+        None => return prereleases != Prereleases::Exclude || !version.is_prerelease(),
+    };
+    if req.matches(version) {
+        return true;
+    }
+
+    prereleases != Prereleases::Exclude
+        && version.is_prerelease()
+        && req.matches(&Version::new(version.major, version.minor, version.patch))
+}
+
+/// The highest of `versions` satisfying `req`, the one with the highest build metadata
+/// of otherwise equal versions.
+pub fn find_matching_version(
+    versions: &[Version],
+    req: Option<&VersionReq>,
+    prereleases: Prereleases,
+) -> Option<Version> {
+    versions
+        .iter()
+        .filter(|version| prereleases != Prereleases::Latest || version.is_prerelease())
+        .filter(|version| matches(version, req, prereleases))
+        .max_by(|a, b| a.cmp(b).then_with(|| a.build.cmp(&b.build)))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_digits_from_text() {
+        use super::Segment::{Number, Text};
+
+        assert_eq!(
+            segments("1.10.2"),
+            vec![
+                Number(1),
+                Text(".".to_owned()),
+                Number(10),
+                Text(".".to_owned()),
+                Number(2)
+            ]
+        );
+        assert_eq!(
+            segments("r27b"),
+            vec![Text("r".to_owned()), Number(27), Text("b".to_owned())]
+        );
+        assert_eq!(segments(""), vec![]);
+    }
+
+    #[test]
+    fn keeps_overlong_numbers_as_text() {
+        assert_eq!(
+            segments("99999999999999999999"),
+            vec![Segment::Text("99999999999999999999".to_owned())]
+        );
+    }
+
+    #[test]
+    fn compares_segments_numerically() {
+        assert!(segments("2024.05") < segments("2024.10"));
+        assert!(segments("r9") < segments("r27"));
+        assert!(segments("1.9") < segments("1.10"));
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert_eq!(
+            placeholder("https://example.com/{version}/tool.zip"),
+            Some("{version}")
+        );
+        assert_eq!(
+            placeholder("https://example.com/{build-meta_1}"),
+            Some("{build-meta_1}")
+        );
+        assert_eq!(
+            placeholder("https://example.com/{ver{sion}"),
+            Some("{sion}")
+        );
+    }
+
+    #[test]
+    fn ignores_braces_which_arent_placeholders() {
+        assert_eq!(placeholder("https://example.com/tool.zip"), None);
+        assert_eq!(placeholder("https://example.com/{}"), None);
+        assert_eq!(placeholder("https://example.com/{a b}"), None);
+        assert_eq!(placeholder("https://example.com/{unterminated"), None);
+        assert_eq!(placeholder("https://example.com/%7Bversion%7D"), None);
+    }
+}
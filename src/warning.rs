@@ -0,0 +1,58 @@
+use crate::checksum::DigestAlgorithm;
+use semver::Version;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Something worth telling the user about that didn't fail the download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadWarning {
+    /// No checksum has been given, so the artifact hasn't been verified.
+    MissingChecksum,
+    /// The artifact has been fetched over plain HTTP.
+    InsecureTransport { url: String },
+    /// The checksum uses a digest that isn't collision resistant.
+    WeakDigest(DigestAlgorithm),
+    /// A mirror has been used because the previous download failed.
+    MirrorFailover { from: String, to: String },
+    /// Signatures are checked, but none has been published for the artifact.
+    MissingSignature,
+    /// The version has been yanked, but was asked for exactly.
+    Yanked(Version),
+    /// The artifact is gone and has been downloaded from a snapshot of the Internet Archive.
+    Archived { url: String },
+    /// The scanner found something in the artifact, but it has been kept.
+    Suspicious { reason: String },
+    /// The destination is locked, e.g. by the running tool on Windows, and is only replaced by
+    /// the artifact on the next reboot.
+    PendingReboot { path: PathBuf },
+}
+
+impl fmt::Display for DownloadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadWarning::MissingChecksum => {
+                write!(f, "No checksum given, artifact is unverified")
+            }
+            DownloadWarning::InsecureTransport { url } => {
+                write!(f, "Downloaded over plain HTTP from {}", url)
+            }
+            DownloadWarning::WeakDigest(algorithm) => {
+                write!(f, "Verified with weak {} digest", algorithm)
+            }
+            DownloadWarning::MirrorFailover { from, to } => {
+                write!(f, "Switched from {} to {}", from, to)
+            }
+            DownloadWarning::MissingSignature => {
+                write!(f, "No signature published for the artifact")
+            }
+            DownloadWarning::Yanked(version) => write!(f, "Version {} has been yanked", version),
+            DownloadWarning::Archived { url } => {
+                write!(f, "Downloaded from the Internet Archive at {}", url)
+            }
+            DownloadWarning::Suspicious { reason } => write!(f, "Suspicious artifact: {}", reason),
+            DownloadWarning::PendingReboot { path } => {
+                write!(f, "{} is locked until the next reboot", path.display())
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Snapshots of the Internet Archive as a last resort for artifacts whose host is gone.
+
+use crate::options::DownloadOptions;
+use just_core::result::BoxedResult;
+
+const AVAILABILITY_API: &str = "https://archive.org/wayback/available";
+
+/// The URL of the unmodified content of the snapshot of `url` closest to now, if there is one.
+pub(crate) fn snapshot(url: &str, options: &DownloadOptions) -> BoxedResult<Option<String>> {
+    use log::debug;
+    use reqwest::Url;
+    use serde_json::Value;
+
+    let api = Url::parse_with_params(AVAILABILITY_API, &[("url", url)])?;
+    debug!("Looking up a snapshot of {}", url);
+    let client = options.client(api.as_str())?;
+    let document: Value = options
+        .send(&client, client.get(api))?
+        .error_for_status()?
+        .json()?;
+
+    let closest = document.pointer("/archived_snapshots/closest");
+    let available = closest
+        .and_then(|closest| closest.get("available"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let timestamp = closest
+        .and_then(|closest| closest.get("timestamp"))
+        .and_then(Value::as_str);
+
+    // `id_` asks for the content as it was archived, without the Wayback Machine's toolbar.
+    Ok(timestamp
+        .filter(|_| available)
+        .map(|timestamp| format!("https://web.archive.org/web/{}id_/{}", timestamp, url)))
+}